@@ -3,11 +3,14 @@ use crate::interpreter::importer::Importer;
 use crate::interpreter::interpreter::GeneralError;
 use crate::interpreter::interpreter::Interpreter;
 use crate::interpreter::interpreter_result::InterpreterResult;
+use crate::parser::parser::Parser;
 use crate::parser::scope::Scope;
+use std::collections::HashSet;
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
+use std::path::PathBuf;
 use walkdir::WalkDir;
 
 impl FileWalker {
@@ -24,36 +27,93 @@ impl FileWalker {
     /// Starts processing files from the given directory
     /// Uses the given `Scope` as the base and exposes it to all files that will be interpreted/// Uses the given scope as the base and exposes it to all files that will be interpreted
     pub fn walk_directory_with_scope(path: &Path, scope: &mut Scope) {
+        FileWalker::walk_directory_with_modes(path, scope, &[]);
+    }
+
+    /// Starts processing files from the given directory
+    /// Uses the given `Scope` as the base, and turns on the given modes in all files that will be interpreted
+    pub fn walk_directory_with_modes(path: &Path, scope: &mut Scope, modes: &[String]) {
         let mut importer = Importer::new(path.to_path_buf());
         println!("Running in {}", path.display());
+        let mut files = vec![];
         for entry in WalkDir::new(path) {
             let file = entry.expect("Read path");
             let path = file.path();
             if path.is_file() {
                 if let Some(extension) = path.extension() {
-                    if extension != "hamster_wheel" {
-                        continue;
-                    }
-
-                    if let Err(e) = FileWalker::handle_file(path, &mut importer, scope) {
-                        eprintln!("{}", e);
+                    if extension == "hamster_wheel" {
+                        files.push(path.to_path_buf());
                     }
                 }
             }
         }
+
+        let partials = FileWalker::find_partials(&files);
+        for path in files {
+            if let Err(e) = FileWalker::handle_file(&path, &mut importer, scope, modes, &partials) {
+                eprintln!("{}", e);
+            }
+        }
         println!("------------------------------------");
     }
 
+    /// Find the files that are included or extended by any of the given files
+    fn find_partials(files: &[PathBuf]) -> HashSet<PathBuf> {
+        let mut partials = HashSet::new();
+        for path in files {
+            let file_content = match fs::read_to_string(path) {
+                Ok(file_content) => file_content,
+                Err(_) => continue,
+            };
+            let parent_path = path.parent().unwrap();
+            for referenced in Parser::new(&file_content).referenced_files() {
+                if let Ok(referenced) = parent_path.join(referenced).canonicalize() {
+                    partials.insert(referenced);
+                }
+            }
+        }
+        partials
+    }
+
+    /// Check if a file is a partial, which is only rendered where it is included or extended
+    fn is_partial(
+        path: &Path,
+        canonical: &Path,
+        file_content: &str,
+        partials: &HashSet<PathBuf>,
+    ) -> bool {
+        let is_referenced = partials.contains(canonical);
+        let is_named_as_partial = match path.file_name() {
+            Some(name) => name.to_string_lossy().starts_with('_'),
+            None => false,
+        };
+        (is_referenced || is_named_as_partial) && !Parser::new(file_content).declares_output()
+    }
+
     fn handle_file(
         path: &Path,
         importer: &mut Importer,
         scope: &mut Scope,
+        modes: &[String],
+        partials: &HashSet<PathBuf>,
     ) -> Result<(), GeneralError> {
         let canonical = path.canonicalize()?;
         let parent_path = path.parent().unwrap();
-        let file_content = fs::read_to_string(canonical)?;
+        let file_content = fs::read_to_string(&canonical)?;
         importer.current_directory = parent_path.to_path_buf();
-        let output = Interpreter::new(&file_content, importer).interpret(scope);
+        if FileWalker::is_partial(path, &canonical, &file_content, partials) {
+            println!("------------------------------------");
+            println!(
+                "Skipping {}, it does not specify an output file and can only be included",
+                path.display()
+            );
+            return Ok(());
+        }
+        let mut interpreter = Interpreter::new(&file_content, importer);
+        for mode in modes {
+            interpreter.turn_on_mode(mode);
+        }
+        let output = interpreter.interpret(scope);
         println!("------------------------------------");
         match output {
             Ok(output) => {
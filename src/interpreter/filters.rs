@@ -0,0 +1,637 @@
+use crate::parser::var_type::Var;
+use crate::parser::var_type::VarType;
+use std::collections::HashSet;
+use std::fmt;
+
+/// The widest that a value can be padded to, so that a typo in a width cannot use up all the memory
+const MAX_PAD_WIDTH: usize = 1_000_000;
+
+/// Apply the filter called `name` to `value`, using the given arguments
+pub fn apply_filter(
+    name: &str,
+    value: VarType,
+    arguments: Vec<VarType>,
+) -> Result<VarType, FilterError> {
+    match name {
+        "upper" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            Ok(text(as_string(name, &value)?.to_uppercase()))
+        }
+        "lower" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            Ok(text(as_string(name, &value)?.to_lowercase()))
+        }
+        "trim" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            Ok(text(as_string(name, &value)?.trim().to_string()))
+        }
+        "replace" => {
+            expect_arguments(name, &arguments, 2, 2)?;
+            let from = as_string(name, &arguments[0])?;
+            let to = as_string(name, &arguments[1])?;
+            Ok(text(as_string(name, &value)?.replace(&from, &to)))
+        }
+        "default" => {
+            expect_arguments(name, &arguments, 1, 1)?;
+            if value.is_empty() {
+                Ok(arguments[0].clone())
+            } else {
+                Ok(value)
+            }
+        }
+        "length" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            let length = match &value {
+                VarType::Row(var) => var.data.len(),
+                VarType::Table(var) => var.data.len(),
+                _ => as_string(name, &value)?.chars().count(),
+            };
+            Ok(VarType::Number(Var::new(length as i64)))
+        }
+        "unique" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            match value {
+                VarType::Row(var) => {
+                    let mut seen = HashSet::new();
+                    let kept: Vec<usize> = (0..var.data.len())
+                        .filter(|&index| seen.insert(&var.data[index]))
+                        .collect();
+                    let headers = var.headers.as_ref().map(|headers| {
+                        kept.iter()
+                            .map(|&index| headers.get(index).cloned().unwrap_or_default())
+                            .collect()
+                    });
+                    let cells = kept.iter().map(|&index| var.data[index].clone()).collect();
+                    Ok(VarType::Row(Var::with_headers(cells, headers)))
+                }
+                VarType::Table(var) => {
+                    let mut seen = HashSet::new();
+                    let rows = var
+                        .data
+                        .iter()
+                        .filter(|row| seen.insert(*row))
+                        .cloned()
+                        .collect();
+                    Ok(VarType::Table(Var::with_headers(rows, var.headers)))
+                }
+                _ => Err(FilterError {
+                    msg: format!("The {} filter can only be used on a collection", name),
+                }),
+            }
+        }
+        "column" => {
+            expect_arguments(name, &arguments, 1, 1)?;
+            let var = as_table(name, value)?;
+            let index = as_column(name, &var, &arguments[0])?;
+            let cells = var
+                .data
+                .iter()
+                .map(|row| row.get(index).cloned().unwrap_or_default())
+                .collect();
+            Ok(VarType::Row(Var::new(cells)))
+        }
+        "lookup" => {
+            expect_arguments(name, &arguments, 2, 2)?;
+            let var = as_table(name, value)?;
+            let index = as_column(name, &var, &arguments[0])?;
+            let key = as_string(name, &arguments[1])?;
+            let row = var
+                .data
+                .into_iter()
+                .find(|row| row.get(index) == Some(&key))
+                .unwrap_or_default();
+            Ok(VarType::Row(Var::with_headers(row, var.headers)))
+        }
+        "transpose" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            let var = as_table(name, value)?;
+            // The column names are transposed along with the rest of the table,
+            // so the first column becomes the column names
+            let has_headers = var.headers.is_some();
+            let mut grid = vec![];
+            grid.extend(var.headers);
+            grid.extend(var.data);
+            let width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+            let mut rows: Vec<Vec<String>> = (0..width)
+                .map(|column| {
+                    grid.iter()
+                        .map(|row| row.get(column).cloned().unwrap_or_default())
+                        .collect()
+                })
+                .collect();
+            if has_headers && !rows.is_empty() {
+                let headers = rows.remove(0);
+                return Ok(VarType::Table(Var::with_headers(rows, Some(headers))));
+            }
+            Ok(VarType::Table(Var::new(rows)))
+        }
+        "truncate" => {
+            expect_arguments(name, &arguments, 1, 2)?;
+            let length = as_count(name, &arguments[0])?;
+            let value = as_string(name, &value)?;
+            if value.chars().count() <= length {
+                return Ok(text(value));
+            }
+            let mut truncated: String = value.chars().take(length).collect();
+            if let Some(end) = arguments.get(1) {
+                truncated.push_str(&as_string(name, end)?);
+            }
+            Ok(text(truncated))
+        }
+        "pad_left" | "pad_right" => {
+            expect_arguments(name, &arguments, 1, 2)?;
+            let width = as_count(name, &arguments[0])?;
+            if width > MAX_PAD_WIDTH {
+                return Err(FilterError {
+                    msg: format!(
+                        "The {} filter cannot pad to a width of {}, the width can be at most {}",
+                        name, width, MAX_PAD_WIDTH
+                    ),
+                });
+            }
+            let padding = match arguments.get(1) {
+                Some(padding) => as_string(name, padding)?,
+                None => String::from(" "),
+            };
+            if padding.is_empty() {
+                return Err(FilterError {
+                    msg: format!("The {} filter cannot pad using an empty string", name),
+                });
+            }
+            let value = as_string(name, &value)?;
+            let missing = width.saturating_sub(value.chars().count());
+            let padding: String = padding.chars().cycle().take(missing).collect();
+            if name == "pad_left" {
+                Ok(text(format!("{}{}", padding, value)))
+            } else {
+                Ok(text(format!("{}{}", value, padding)))
+            }
+        }
+        "camel_case" | "pascal_case" | "snake_case" | "screaming_snake_case" | "kebab_case" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            Ok(text(convert_case(name, &as_string(name, &value)?)))
+        }
+        "raw" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            Ok(value)
+        }
+        "json" | "js" | "xml" | "html" | "csv" | "rust" | "swift" | "kotlin" => {
+            expect_arguments(name, &arguments, 0, 0)?;
+            Ok(text(escape(name, &as_string(name, &value)?)))
+        }
+        _ => Err(FilterError {
+            msg: format!("Unknown filter {}", name),
+        }),
+    }
+}
+
+/// Find a row of the table `value` that is too short to have the column that the `column`
+/// filter is given in `arguments`. Return the index of the column and the length of that row
+pub fn missing_column(value: &VarType, arguments: &[VarType]) -> Option<(usize, usize)> {
+    let var = match value {
+        VarType::Table(var) => var,
+        _ => return None,
+    };
+    let index = as_column("column", var, arguments.first()?).ok()?;
+    var.data
+        .iter()
+        .find(|row| index >= row.len())
+        .map(|row| (index, row.len()))
+}
+
+/// Whether the filter called `name` escapes a value for a target language,
+/// or marks it as `raw` so that it is left untouched
+pub fn is_escaping_filter(name: &str) -> bool {
+    matches!(
+        name,
+        "raw" | "json" | "js" | "xml" | "html" | "csv" | "rust" | "swift" | "kotlin"
+    )
+}
+
+/// Escape `value` so that it can be written inside a string literal of the language
+/// named by the given filter. The `csv` escaper quotes the whole field when it needs to.
+fn escape(name: &str, value: &str) -> String {
+    if name == "csv" {
+        if value.contains(&[',', '"', '\n', '\r'][..]) {
+            return format!("\"{}\"", value.replace('"', "\"\""));
+        }
+        return value.to_string();
+    }
+
+    let mut result = String::new();
+    for c in value.chars() {
+        let escaped = match (name, c) {
+            ("xml", '\'') => String::from("&apos;"),
+            ("html", '\'') => String::from("&#39;"),
+            ("xml", '&') | ("html", '&') => String::from("&amp;"),
+            ("xml", '<') | ("html", '<') => String::from("&lt;"),
+            ("xml", '>') | ("html", '>') => String::from("&gt;"),
+            ("xml", '"') | ("html", '"') => String::from("&quot;"),
+            ("xml", _) | ("html", _) => c.to_string(),
+            (_, '\\') => String::from("\\\\"),
+            (_, '"') => String::from("\\\""),
+            (_, '\n') => String::from("\\n"),
+            (_, '\r') => String::from("\\r"),
+            (_, '\t') => String::from("\\t"),
+            ("js", '\'') => String::from("\\'"),
+            ("js", '`') => String::from("\\`"),
+            ("js", '\u{2028}') => String::from("\\u2028"),
+            ("js", '\u{2029}') => String::from("\\u2029"),
+            ("kotlin", '$') => String::from("\\$"),
+            (_, c) if c.is_control() => match name {
+                "rust" | "swift" => format!("\\u{{{:x}}}", c as u32),
+                _ => format!("\\u{:04x}", c as u32),
+            },
+            (_, c) => c.to_string(),
+        };
+        result.push_str(&escaped);
+    }
+    result
+}
+
+/// Join the words of `value` using the identifier convention named by the given filter
+fn convert_case(name: &str, value: &str) -> String {
+    let words = split_words(value);
+    match name {
+        "camel_case" => words
+            .iter()
+            .enumerate()
+            .map(|(index, word)| {
+                if index == 0 {
+                    word.to_lowercase()
+                } else {
+                    capitalize(word)
+                }
+            })
+            .collect(),
+        "pascal_case" => words.iter().map(|word| capitalize(word)).collect(),
+        "snake_case" => words
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<String>>()
+            .join("_"),
+        "screaming_snake_case" => words
+            .iter()
+            .map(|word| word.to_uppercase())
+            .collect::<Vec<String>>()
+            .join("_"),
+        _ => words
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<String>>()
+            .join("-"),
+    }
+}
+
+/// Split a value into words. Anything that is not a letter or a digit separates words,
+/// and so do changes in case: "welcomeScreen" and "HTTPServer" are two words each.
+/// Digits stay attached to the word they follow, as in "utf8" or "2nd".
+fn split_words(value: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let chars: Vec<char> = value.chars().collect();
+    for (index, c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(word);
+                word = String::new();
+            }
+            continue;
+        }
+
+        if let Some(previous) = word.chars().last() {
+            let next_is_lowercase =
+                matches!(chars.get(index + 1), Some(next) if next.is_lowercase());
+            let starts_word = c.is_uppercase()
+                && (previous.is_lowercase()
+                    || previous.is_numeric()
+                    || (previous.is_uppercase() && next_is_lowercase));
+            if starts_word {
+                words.push(word);
+                word = String::new();
+            }
+        }
+        word.push(*c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => format!("{}{}", first.to_uppercase(), chars.as_str().to_lowercase()),
+        None => String::new(),
+    }
+}
+
+fn text(value: String) -> VarType {
+    VarType::Value(Var::new(value))
+}
+
+fn expect_arguments(
+    name: &str,
+    arguments: &[VarType],
+    min: usize,
+    max: usize,
+) -> Result<(), FilterError> {
+    if arguments.len() >= min && arguments.len() <= max {
+        return Ok(());
+    }
+    let expected = if min == max {
+        format!("{}", min)
+    } else {
+        format!("{} to {}", min, max)
+    };
+    Err(FilterError {
+        msg: format!(
+            "The {} filter expects {} arguments, found {}",
+            name,
+            expected,
+            arguments.len()
+        ),
+    })
+}
+
+fn as_string(name: &str, value: &VarType) -> Result<String, FilterError> {
+    match value {
+        VarType::Value(var) => Ok(var.data.clone()),
+        VarType::Number(var) => Ok(var.data.to_string()),
+        VarType::Bool(var) => Ok(var.data.to_string()),
+        _ => Err(FilterError {
+            msg: format!("The {} filter can only be used on text", name),
+        }),
+    }
+}
+
+fn as_table(name: &str, value: VarType) -> Result<Var<Vec<Vec<String>>>, FilterError> {
+    match value {
+        VarType::Table(var) => Ok(var),
+        _ => Err(FilterError {
+            msg: format!("The {} filter can only be used on a table", name),
+        }),
+    }
+}
+
+/// Find the index of a column of a table from its number, where negative numbers count from
+/// the last column, or from its name if the table has column names
+fn as_column(
+    name: &str,
+    table: &Var<Vec<Vec<String>>>,
+    value: &VarType,
+) -> Result<usize, FilterError> {
+    let number = match value {
+        VarType::Number(var) => var.data,
+        VarType::Value(var) => match var.data.trim().parse::<i64>() {
+            Ok(number) => number,
+            Err(_) => {
+                return table.column_index(&var.data).ok_or_else(|| FilterError {
+                    msg: format!("Unknown column {}", var.data),
+                })
+            }
+        },
+        _ => {
+            return Err(FilterError {
+                msg: format!("The {} filter expects a column number or name", name),
+            })
+        }
+    };
+    if number >= 0 {
+        return Ok(number as usize);
+    }
+    let width = match &table.headers {
+        Some(headers) => headers.len(),
+        None => table.data.iter().map(|row| row.len()).max().unwrap_or(0),
+    };
+    let index = number + width as i64;
+    if index < 0 {
+        return Err(FilterError {
+            msg: format!(
+                "The column {} is out of bounds of a table with {} columns",
+                number, width
+            ),
+        });
+    }
+    Ok(index as usize)
+}
+
+fn as_count(name: &str, value: &VarType) -> Result<usize, FilterError> {
+    match value {
+        VarType::Number(var) if var.data >= 0 => Ok(var.data as usize),
+        VarType::Value(var) => var.data.trim().parse::<usize>().map_err(|_| FilterError {
+            msg: format!("The {} filter expects a number, found {}", name, var.data),
+        }),
+        _ => Err(FilterError {
+            msg: format!("The {} filter expects a number", name),
+        }),
+    }
+}
+
+pub struct FilterError {
+    msg: String,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl fmt::Debug for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{ file: {}, line: {} }}", file!(), line!())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(name: &str, value: &str, arguments: Vec<VarType>) -> String {
+        match apply_filter(name, text(String::from(value)), arguments) {
+            Ok(VarType::Value(var)) => var.data,
+            Ok(VarType::Number(var)) => var.data.to_string(),
+            _ => String::from("error"),
+        }
+    }
+
+    #[test]
+    fn text_filters() {
+        assert_eq!(apply("upper", "Title", vec![]), "TITLE");
+        assert_eq!(apply("lower", "Title", vec![]), "title");
+        assert_eq!(apply("trim", "  Title ", vec![]), "Title");
+        assert_eq!(apply("length", "عنوان", vec![]), "5");
+        assert_eq!(
+            apply(
+                "replace",
+                "a-b-c",
+                vec![text(String::from("-")), text(String::from("_"))]
+            ),
+            "a_b_c"
+        );
+    }
+
+    #[test]
+    fn default_filter_only_replaces_empty_values() {
+        assert_eq!(
+            apply("default", "", vec![text(String::from("none"))]),
+            "none"
+        );
+        assert_eq!(apply("default", "x", vec![text(String::from("none"))]), "x");
+    }
+
+    #[test]
+    fn size_filters() {
+        let three = VarType::Number(Var::new(3));
+        assert_eq!(apply("truncate", "Title", vec![three.clone()]), "Tit");
+        assert_eq!(
+            apply(
+                "truncate",
+                "Title",
+                vec![three.clone(), text(String::from("..."))]
+            ),
+            "Tit..."
+        );
+        assert_eq!(apply("truncate", "Ti", vec![three.clone()]), "Ti");
+        assert_eq!(
+            apply(
+                "pad_left",
+                "7",
+                vec![three.clone(), text(String::from("0"))]
+            ),
+            "007"
+        );
+        assert_eq!(apply("pad_right", "7", vec![three]), "7  ");
+        let width = VarType::Number(Var::new(10_000_000_000));
+        match apply_filter("pad_left", text(String::from("7")), vec![width]) {
+            Err(error) => assert_eq!(
+                error.msg,
+                "The pad_left filter cannot pad to a width of 10000000000, the width can be at most 1000000"
+            ),
+            Ok(_) => panic!("A width over the limit is padded"),
+        }
+    }
+
+    #[test]
+    fn case_filters() {
+        let value = "Welcome Screen Title";
+        assert_eq!(apply("camel_case", value, vec![]), "welcomeScreenTitle");
+        assert_eq!(apply("pascal_case", value, vec![]), "WelcomeScreenTitle");
+        assert_eq!(apply("snake_case", value, vec![]), "welcome_screen_title");
+        assert_eq!(
+            apply("screaming_snake_case", value, vec![]),
+            "WELCOME_SCREEN_TITLE"
+        );
+        assert_eq!(apply("kebab_case", value, vec![]), "welcome-screen-title");
+    }
+
+    #[test]
+    fn case_filters_split_on_case_changes() {
+        assert_eq!(
+            apply("snake_case", "parseHTTPServer", vec![]),
+            "parse_http_server"
+        );
+        assert_eq!(apply("camel_case", "user_ID2Name", vec![]), "userId2Name");
+        assert_eq!(
+            apply("pascal_case", "utf8 decoder 2nd", vec![]),
+            "Utf8Decoder2nd"
+        );
+        assert_eq!(
+            apply("kebab_case", "Ärger über--Straße", vec![]),
+            "ärger-über-straße"
+        );
+        assert_eq!(apply("snake_case", "عنوان الصفحة", vec![]), "عنوان_الصفحة");
+    }
+
+    #[test]
+    fn escaping_filters() {
+        let value = "Say \"hi\"\n\\ 'it's' <b> & $x";
+        assert_eq!(
+            apply("json", value, vec![]),
+            "Say \\\"hi\\\"\\n\\\\ 'it's' <b> & $x"
+        );
+        assert_eq!(
+            apply("js", value, vec![]),
+            "Say \\\"hi\\\"\\n\\\\ \\'it\\'s\\' <b> & $x"
+        );
+        assert_eq!(
+            apply("kotlin", value, vec![]),
+            "Say \\\"hi\\\"\\n\\\\ 'it's' <b> & \\$x"
+        );
+        assert_eq!(
+            apply("html", value, vec![]),
+            "Say &quot;hi&quot;\n\\ &#39;it&#39;s&#39; &lt;b&gt; &amp; $x"
+        );
+        assert_eq!(apply("rust", "bell\u{7}", vec![]), "bell\\u{7}");
+        assert_eq!(apply("json", "bell\u{7}", vec![]), "bell\\u0007");
+        assert_eq!(apply("csv", "a,\"b\"", vec![]), "\"a,\"\"b\"\"\"");
+        assert_eq!(apply("csv", "plain", vec![]), "plain");
+    }
+
+    #[test]
+    fn unique_filter_keeps_the_first_of_each_item() {
+        let cells = |cells: &[&str]| cells.iter().map(|cell| cell.to_string()).collect();
+        let row = VarType::Row(Var::with_headers(
+            cells(&["a", "b", "a", "c"]),
+            Some(cells(&["1", "2", "3", "4"])),
+        ));
+        match apply_filter("unique", row, vec![]) {
+            Ok(VarType::Row(var)) => {
+                assert_eq!(var.data, cells(&["a", "b", "c"]));
+                assert_eq!(var.headers, Some(cells(&["1", "2", "4"])));
+            }
+            _ => panic!("Expected a row"),
+        }
+        let table = VarType::Table(Var::new(vec![cells(&["a"]), cells(&["a"]), cells(&["b"])]));
+        match apply_filter("unique", table, vec![]) {
+            Ok(VarType::Table(var)) => assert_eq!(var.data, vec![cells(&["a"]), cells(&["b"])]),
+            _ => panic!("Expected a table"),
+        }
+        assert_eq!(apply("unique", "a", vec![]), "error");
+    }
+
+    #[test]
+    fn columns_and_transposed_tables() {
+        let cells =
+            |cells: &[&str]| -> Vec<String> { cells.iter().map(|cell| cell.to_string()).collect() };
+        let table = VarType::Table(Var::with_headers(
+            vec![cells(&["title", "Title", "عنوان"]), cells(&["ok", "OK"])],
+            Some(cells(&["key", "en", "ar"])),
+        ));
+        let column = |argument: VarType| match apply_filter("column", table.clone(), vec![argument])
+        {
+            Ok(VarType::Row(var)) => var.data,
+            _ => vec![String::from("error")],
+        };
+        assert_eq!(
+            column(VarType::Number(Var::new(1))),
+            cells(&["Title", "OK"])
+        );
+        assert_eq!(column(VarType::Number(Var::new(-1))), cells(&["عنوان", ""]));
+        assert_eq!(column(text(String::from("key"))), cells(&["title", "ok"]));
+        assert_eq!(column(text(String::from("fr"))), cells(&["error"]));
+
+        match apply_filter("transpose", table, vec![]) {
+            Ok(VarType::Table(var)) => {
+                assert_eq!(var.headers, Some(cells(&["key", "title", "ok"])));
+                assert_eq!(
+                    var.data,
+                    vec![cells(&["en", "Title", "OK"]), cells(&["ar", "عنوان", ""])]
+                );
+            }
+            _ => panic!("Expected a table"),
+        }
+        assert_eq!(apply("transpose", "a", vec![]), "error");
+    }
+
+    #[test]
+    fn unknown_filter() {
+        assert_eq!(apply("shout", "Title", vec![]), "error");
+        assert_eq!(
+            apply("upper", "Title", vec![text(String::from("x"))]),
+            "error"
+        );
+    }
+}
@@ -23,12 +23,18 @@ impl Importer {
 
     pub fn update_scope(&mut self, scope: &mut Scope, imports: Vec<ImportExpr>) {
         for import in imports {
+            let has_headers = match import.value_of("headers") {
+                Some(headers) => headers.trim().eq_ignore_ascii_case("true"),
+                None => false,
+            };
             if let Some(name) = import.value_of("name") {
                 if let Some(filename) = import.value_of("path") {
-                    scope.insert(name, VarType::Table(Var::new(self.get_from_file(filename))));
+                    let records = self.get_from_file(filename);
+                    scope.insert(name, Importer::to_table(records, has_headers));
                 } else {
                     if let Some(key) = import.value_of("key") {
-                        scope.insert(name, VarType::Table(Var::new(self.get_google_sheet(key))));
+                        let records = self.get_google_sheet(key);
+                        scope.insert(name, Importer::to_table(records, has_headers));
                     } else {
                         println!("Unusable import, specify a file path \"path\" or a google sheets key \"key\" (The long gibberish string in the sheet's url)");
                     }
@@ -39,6 +45,16 @@ impl Importer {
         }
     }
 
+    /// Wrap records in a table, using the first record as the column names if `has_headers` is set
+    fn to_table(mut records: Vec<Vec<String>>, has_headers: bool) -> VarType {
+        if has_headers && !records.is_empty() {
+            let headers = records.remove(0);
+            VarType::Table(Var::with_headers(records, Some(headers)))
+        } else {
+            VarType::Table(Var::new(records))
+        }
+    }
+
     pub fn update_with_file(&mut self,scope: &mut Scope, variable_name: String, filename: String) {
         scope.insert(variable_name, VarType::Table(Var::new(self.get_from_file(filename))));
     }
@@ -1,5 +1,7 @@
+use crate::interpreter::filters;
 use crate::interpreter::importer::Importer;
 use crate::interpreter::interpreter_result::InterpreterResult;
+use crate::interpreter::loop_iterator;
 use crate::interpreter::loop_iterator::LoopIterator;
 use crate::parser::parser::ParseError;
 use crate::parser::parser::Parser;
@@ -9,13 +11,44 @@ use crate::parser::var_type::VarType;
 use crate::tokenizer::tokenizer::InfoToken;
 use crate::tokenizer::tokenizer::Token;
 use crate::tree_nodes::tree_nodes::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// The most numbers a range can have, so that a typo in a bound cannot use up all the memory
+const MAX_RANGE_LENGTH: usize = 1_000_000;
 
 pub struct Interpreter<'a> {
     pub text: &'a str,
     parser: Parser<'a>,
     output_file: String,
+    escaper: Option<String>,
     importer: &'a mut Importer,
+    include_stack: Vec<PathBuf>,
+    macros: HashMap<String, Rc<Macro>>,
+    call_stack: Vec<String>,
+    overrides: HashMap<String, Rc<BlockOverride>>,
+    clamp: bool,
+    strict: bool,
+}
+
+/// A macro along with the text of the file it was defined in
+struct Macro {
+    text: String,
+    define_expr: DefineExpr,
+}
+
+/// A `BLOCK` that replaces the block of the same name in the file that is extended,
+/// along with the text of the file it was written in
+struct BlockOverride {
+    text: String,
+    named_block_expr: NamedBlockExpr,
 }
 
 impl<'a> Interpreter<'a> {
@@ -25,26 +58,112 @@ impl<'a> Interpreter<'a> {
             text,
             parser: Parser::new(text),
             output_file: String::from(""),
+            escaper: None,
             importer,
+            include_stack: vec![],
+            macros: HashMap::new(),
+            call_stack: vec![],
+            overrides: HashMap::new(),
+            clamp: false,
+            strict: false,
         }
     }
 
-    /// Interpet this `Interpreter`'s source text using the given `Scope` as a base
+    /// Turn on the mode with the given name, as if the file started with `{{ MODE name }}`.
+    /// Returns `false` if there is no mode with that name
+    pub fn turn_on_mode(&mut self, name: &str) -> bool {
+        match name {
+            "clamp" => self.clamp = true,
+            "strict" => self.strict = true,
+            _ => return false,
+        };
+        true
+    }
+
+    /// Interpet this `Interpreter`'s source text using the given `Scope` as a base. What the
+    /// file sets or imports is kept in a scope of its own, so other files that use the same
+    /// base do not see it
     pub fn interpret(&mut self, base_scope: &mut Scope) -> Result<InterpreterResult, GeneralError> {
         let expr = self.parser.parse()?;
+        let mut scope = Scope::with_parent(base_scope);
         Ok(InterpreterResult {
-            text: self.visit_expr(base_scope, expr)?,
+            text: self.visit_expr(&mut scope, expr)?,
             output_file: self.output_file.clone(),
         })
     }
 
+    /// Interpret this `Interpreter`'s source text as a partial that is included in another file
+    fn interpret_partial(&mut self, scope: &mut Scope) -> Result<String, GeneralError> {
+        let expr = self.parser.parse_partial()?;
+        Ok(self.visit_top_level_block(scope, expr)?)
+    }
+
     fn visit_expr(&mut self, scope: &mut Scope, expr: Expr) -> Result<String, InterpreterError> {
         match expr {
             Expr::Start(node) => self.visit_start(scope, node),
             Expr::Anything(node) => Ok(self.visit_anything(node)),
+            Expr::Raw(node) => Ok(self.visit_raw(node)),
+            Expr::Include(node) => self.visit_include(scope, node),
+            Expr::Define(node) => Ok(self.visit_define(node)),
+            Expr::Mode(node) => self.visit_mode(node),
+            Expr::Call(node) => self.visit_call(scope, node),
+            Expr::NamedBlock(node) => self.visit_named_block(scope, node),
+            Expr::Extends(node) => Err(InterpreterError {
+                msg: String::from("EXTENDS can only be used once, at the top level of a file"),
+                line_number: self.get_line_number_for_token(node.extends),
+            }),
             Expr::Block(node) => self.visit_block(scope, node),
             Expr::MustacheAccessor(node) => self.visit_mustache_accessor(scope, node),
             Expr::Loop(node) => self.visit_loop(scope, node),
+            Expr::If(node) => self.visit_if(scope, node),
+            Expr::Binary(node) => {
+                let info_token = node.operator.clone();
+                let value = self.visit_binary(scope, node)?;
+                self.var_to_string(value, info_token)
+            }
+            Expr::Unary(node) => {
+                let info_token = node.operator.clone();
+                let value = self.visit_unary(scope, node)?;
+                self.var_to_string(value, info_token)
+            }
+            Expr::IsEmpty(node) => {
+                let info_token = node.empty.clone();
+                let value = self.visit_is_empty(scope, node)?;
+                self.var_to_string(value, info_token)
+            }
+            Expr::Literal(node) => {
+                let info_token = node.token.clone();
+                let value = self.visit_literal(node)?;
+                self.var_to_string(value, info_token)
+            }
+            Expr::Accessor(node) => self.visit_accessor(scope, node),
+            Expr::Slice(node) => {
+                let info_token = node.variable.clone();
+                let value = self.visit_slice(scope, *node)?;
+                self.var_to_string(value, info_token)
+            }
+            Expr::Function(node) => {
+                let info_token = node.name.clone();
+                let value = self.visit_function(scope, node)?;
+                self.var_to_string(value, info_token)
+            }
+            Expr::Set(node) => {
+                self.visit_set(scope, node)?;
+                Ok(String::from(""))
+            }
+        }
+    }
+
+    fn visit_value(&mut self, scope: &mut Scope, expr: Expr) -> Result<VarType, InterpreterError> {
+        match expr {
+            Expr::Binary(node) => self.visit_binary(scope, node),
+            Expr::Unary(node) => self.visit_unary(scope, node),
+            Expr::IsEmpty(node) => self.visit_is_empty(scope, node),
+            Expr::Literal(node) => self.visit_literal(node),
+            Expr::Accessor(node) => self.visit_accessor_value(scope, node),
+            Expr::Slice(node) => self.visit_slice(scope, *node),
+            Expr::Function(node) => self.visit_function(scope, node),
+            _ => Ok(VarType::Value(Var::new(self.visit_expr(scope, expr)?))),
         }
     }
 
@@ -54,7 +173,45 @@ impl<'a> Interpreter<'a> {
         start_expr: Box<StartExpr>,
     ) -> Result<String, InterpreterError> {
         self.output_file = start_expr.output.file_path.slice;
-        self.visit_expr(scope, start_expr.expr)
+        if let Some(escape) = start_expr.escape {
+            if !filters::is_escaping_filter(&escape.escaper.slice) {
+                return Err(InterpreterError {
+                    msg: format!("Unknown escaper {}", escape.escaper.slice),
+                    line_number: self.get_line_number_for_token(escape.escaper),
+                });
+            }
+            self.escaper = Some(escape.escaper.slice);
+        }
+        self.visit_top_level_block(scope, start_expr.expr)
+    }
+
+    /// Render the block at the top of a file, which is the only block that can use `EXTENDS`
+    fn visit_top_level_block(
+        &mut self,
+        scope: &mut Scope,
+        expr: Expr,
+    ) -> Result<String, InterpreterError> {
+        let block_expr = match expr {
+            Expr::Block(block_expr) => block_expr,
+            _ => return self.visit_expr(scope, expr),
+        };
+        // Macros that are defined at the top level of a file can be called anywhere in it
+        for expr in &block_expr.blocks {
+            if let Expr::Define(node) = expr {
+                self.visit_define(node.clone());
+            }
+        }
+        let extends_index = block_expr
+            .blocks
+            .iter()
+            .position(|expr| matches!(expr, Expr::Extends(_)));
+        match extends_index {
+            Some(index) => {
+                self.importer.update_scope(scope, block_expr.imports);
+                self.visit_extends(scope, block_expr.blocks, index)
+            }
+            None => self.visit_block(scope, block_expr),
+        }
     }
 
     fn visit_block(
@@ -68,28 +225,419 @@ impl<'a> Interpreter<'a> {
         self.importer.update_scope(scope, block_expr.imports);
 
         for expr in exprs {
+            // Variables that are set are available in the rest of the block
+            if let Expr::Set(node) = expr {
+                self.visit_set(scope, node)?;
+                continue;
+            }
             let mut child_scope = Scope::with_parent(scope);
             strings.push(self.visit_expr(&mut child_scope, expr)?);
         }
         Ok(strings.join(""))
     }
 
+    /// Render the file that is extended by the block `exprs` in place of the block. Only the
+    /// `BLOCK`s in `exprs` are kept to override the ones in the extended file, along with the
+    /// `DEFINE`s, so everything else in `exprs` is ignored
+    fn visit_extends(
+        &mut self,
+        scope: &mut Scope,
+        exprs: Vec<Expr>,
+        extends_index: usize,
+    ) -> Result<String, InterpreterError> {
+        let mut extends_expr = None;
+        for (index, expr) in exprs.into_iter().enumerate() {
+            match expr {
+                Expr::Extends(node) if index == extends_index => extends_expr = Some(node),
+                Expr::Extends(node) => {
+                    return Err(InterpreterError {
+                        msg: String::from("A file can only extend one other file"),
+                        line_number: self.get_line_number_for_token(node.extends),
+                    })
+                }
+                Expr::NamedBlock(node) => {
+                    let block_override = BlockOverride {
+                        text: self.text.to_string(),
+                        named_block_expr: *node,
+                    };
+                    // Blocks from files that extend this one take priority
+                    self.overrides
+                        .entry(block_override.named_block_expr.name.slice.clone())
+                        .or_insert_with(|| Rc::new(block_override));
+                }
+                Expr::Define(node) => {
+                    self.visit_define(node);
+                }
+                Expr::Set(node) => self.visit_set(scope, node)?,
+                Expr::Mode(node) => {
+                    self.visit_mode(node)?;
+                }
+                _ => (),
+            };
+        }
+        let extends_expr = extends_expr.expect("The extends expression is in the block");
+        self.render_partial(scope, extends_expr.file_path, "extend")
+    }
+
+    fn visit_named_block(
+        &mut self,
+        scope: &mut Scope,
+        named_block_expr: Box<NamedBlockExpr>,
+    ) -> Result<String, InterpreterError> {
+        let name = named_block_expr.name.slice.clone();
+        let block_override = match self.overrides.get(&name) {
+            Some(block_override) => Rc::clone(block_override),
+            None => return self.visit_expr(scope, *named_block_expr.block),
+        };
+        let line_number = self.get_line_number_for_token(named_block_expr.name);
+        let block = *block_override.named_block_expr.block.clone();
+        self.child_interpreter(&block_override.text)
+            .visit_expr(scope, block)
+            .map_err(|error| InterpreterError {
+                msg: format!("Failed to render the block {}\n{}", name, error),
+                line_number,
+            })
+    }
+
+    fn visit_set(
+        &mut self,
+        scope: &mut Scope,
+        set_expr: Box<SetExpr>,
+    ) -> Result<(), InterpreterError> {
+        let value = self.visit_filtered_value(scope, set_expr.value, set_expr.filters)?;
+        scope.insert(set_expr.name.slice, value);
+        Ok(())
+    }
+
     fn visit_anything(&self, anything_expr: Box<AnythingExpr>) -> String {
-        if anything_expr.tokens.len() > 0 {
-            self.text[anything_expr.tokens[0].start
-                ..anything_expr.tokens[anything_expr.tokens.len() - 1].end]
-                .to_string()
+        anything_expr
+            .tokens
+            .iter()
+            .map(|token| token.slice.as_str())
+            .collect()
+    }
+
+    fn visit_raw(&self, raw_expr: Box<RawExpr>) -> String {
+        match raw_expr.text {
+            Some(text) => text.slice,
+            None => String::from(""),
+        }
+    }
+
+    fn visit_include(
+        &mut self,
+        scope: &mut Scope,
+        include_expr: Box<IncludeExpr>,
+    ) -> Result<String, InterpreterError> {
+        self.render_partial(scope, include_expr.file_path, "include")
+    }
+
+    /// Render the partial at `file_path` in the given `Scope`, resolving the path
+    /// relative to the directory of the current file
+    fn render_partial(
+        &mut self,
+        scope: &mut Scope,
+        file_path: InfoToken,
+        action: &str,
+    ) -> Result<String, InterpreterError> {
+        let line_number = self.get_line_number_for_token(file_path.clone());
+        let path = Path::new(&file_path.slice);
+        let path = if path.is_absolute() {
+            path.to_path_buf()
         } else {
-            String::from("")
+            self.importer.current_directory.join(path)
+        };
+        let content = path
+            .canonicalize()
+            .and_then(|path| Ok((fs::read_to_string(&path)?, path)));
+        let (content, path) = match content {
+            Ok(content) => content,
+            Err(_) => {
+                return Err(InterpreterError {
+                    msg: format!("Failed to read the file {}", file_path.slice),
+                    line_number,
+                })
+            }
+        };
+        if self.include_stack.contains(&path) {
+            let cycle: Vec<String> = self
+                .include_stack
+                .iter()
+                .skip_while(|included| **included != path)
+                .chain(std::iter::once(&path))
+                .map(|included| included.display().to_string())
+                .collect();
+            return Err(InterpreterError {
+                msg: format!("Include cycle detected: {}", cycle.join(" -> ")),
+                line_number,
+            });
+        }
+
+        let current_directory = self.importer.current_directory.clone();
+        if let Some(parent) = path.parent() {
+            self.importer.current_directory = parent.to_path_buf();
+        }
+        let mut interpreter = self.child_interpreter(&content);
+        interpreter.include_stack.push(path);
+        let result = interpreter.interpret_partial(scope);
+        // Macros that are defined in other files can be used by the file that renders them
+        let macros = interpreter.macros;
+        self.macros = macros;
+        self.importer.current_directory = current_directory;
+
+        result.map_err(|error| InterpreterError {
+            msg: format!("Failed to {} {}\n{}", action, file_path.slice, error.msg),
+            line_number,
+        })
+    }
+
+    /// Construct an `Interpreter` for text that comes from another file,
+    /// which shares the state of this `Interpreter`
+    fn child_interpreter<'b>(&'b mut self, text: &'b str) -> Interpreter<'b> {
+        Interpreter {
+            text,
+            parser: Parser::new(text),
+            output_file: String::from(""),
+            escaper: self.escaper.clone(),
+            importer: self.importer,
+            include_stack: self.include_stack.clone(),
+            macros: self.macros.clone(),
+            call_stack: self.call_stack.clone(),
+            overrides: self.overrides.clone(),
+            clamp: self.clamp,
+            strict: self.strict,
+        }
+    }
+
+    fn visit_define(&mut self, define_expr: Box<DefineExpr>) -> String {
+        let name = define_expr.name.slice.clone();
+        let definition = Macro {
+            text: self.text.to_string(),
+            define_expr: *define_expr,
+        };
+        self.macros.insert(name, Rc::new(definition));
+        String::from("")
+    }
+
+    /// Turn on a mode for the rest of the file
+    fn visit_mode(&mut self, mode_expr: Box<ModeExpr>) -> Result<String, InterpreterError> {
+        if !self.turn_on_mode(&mode_expr.name.slice) {
+            return Err(InterpreterError {
+                msg: format!("Unknown mode {}", mode_expr.name.slice),
+                line_number: self.get_line_number_for_token(mode_expr.name),
+            });
+        }
+        Ok(String::from(""))
+    }
+
+    fn visit_call(
+        &mut self,
+        scope: &mut Scope,
+        call_expr: Box<CallExpr>,
+    ) -> Result<String, InterpreterError> {
+        let name = call_expr.name.slice.clone();
+        let line_number = self.get_line_number_for_token(call_expr.name.clone());
+        let definition = match self.macros.get(&name) {
+            Some(definition) => Rc::clone(definition),
+            None => {
+                return Err(InterpreterError {
+                    msg: format!("Unknown macro {}", name),
+                    line_number,
+                })
+            }
+        };
+        if self.call_stack.contains(&name) {
+            return Err(InterpreterError {
+                msg: format!("The macro {} cannot call itself", name),
+                line_number,
+            });
         }
+        let parameters = &definition.define_expr.parameters;
+        let arguments = call_expr.arguments.arguments;
+        if parameters.len() != arguments.len() {
+            return Err(InterpreterError {
+                msg: format!(
+                    "The macro {} expects {} arguments, found {}",
+                    name,
+                    parameters.len(),
+                    arguments.len()
+                ),
+                line_number,
+            });
+        }
+
+        let mut values = vec![];
+        for argument in arguments {
+            values.push(self.visit_value(scope, argument)?);
+        }
+        let mut child_scope = Scope::with_parent(scope);
+        for (parameter, value) in parameters.iter().zip(values) {
+            child_scope.insert(parameter.slice.clone(), value);
+        }
+        let mut interpreter = self.child_interpreter(&definition.text);
+        interpreter.call_stack.push(name.clone());
+        let block = *definition.define_expr.block.clone();
+
+        let result = interpreter.visit_expr(&mut child_scope, block);
+        // Macros that are defined by the macro can be called after it
+        let macros = interpreter.macros;
+        self.macros = macros;
+        result.map_err(|error| InterpreterError {
+            msg: format!("Failed to call {}\n{}", name, error),
+            line_number,
+        })
     }
 
     fn visit_mustache_accessor(
         &mut self,
         scope: &mut Scope,
-        mustache_accessor_expr: MustacheAccessorExpr,
+        mustache_accessor_expr: Box<MustacheAccessorExpr>,
     ) -> Result<String, InterpreterError> {
-        self.visit_accessor(scope, mustache_accessor_expr.accessor)
+        let info_token = mustache_accessor_expr.left_mustache.clone();
+        let escaped = mustache_accessor_expr
+            .filters
+            .iter()
+            .any(|filter| filters::is_escaping_filter(&filter.name.slice))
+            || match &mustache_accessor_expr.value {
+                Expr::Function(function_expr) => {
+                    filters::is_escaping_filter(&function_expr.name.slice)
+                }
+                _ => false,
+            };
+        let mut variable = self.visit_filtered_value(
+            scope,
+            mustache_accessor_expr.value,
+            mustache_accessor_expr.filters,
+        )?;
+        if let Some(escaper) = &self.escaper {
+            if !escaped {
+                variable = match filters::apply_filter(escaper, variable, vec![]) {
+                    Ok(variable) => variable,
+                    Err(e) => {
+                        return Err(InterpreterError {
+                            msg: format!("{}", e),
+                            line_number: self.get_line_number_for_token(info_token),
+                        })
+                    }
+                };
+            }
+        }
+        self.var_to_string(variable, info_token)
+    }
+
+    /// Evaluate a value and pass it through its filters. Missing cells and rows are allowed
+    /// in strict mode if the value has a `default` filter, since it replaces them
+    fn visit_filtered_value(
+        &mut self,
+        scope: &mut Scope,
+        value: Expr,
+        filters: Vec<FilterExpr>,
+    ) -> Result<VarType, InterpreterError> {
+        let strict = self.strict;
+        if filters.iter().any(|filter| filter.name.slice == "default") {
+            self.strict = false;
+        }
+        let value = match self.visit_value(scope, value) {
+            Ok(value) => self.visit_filters(scope, value, filters),
+            Err(error) => Err(error),
+        };
+        self.strict = strict;
+        value
+    }
+
+    fn visit_filters(
+        &mut self,
+        scope: &mut Scope,
+        variable: VarType,
+        filters: Vec<FilterExpr>,
+    ) -> Result<VarType, InterpreterError> {
+        let mut variable = variable;
+        for filter in filters {
+            let mut arguments = vec![];
+            if let Some(arguments_expr) = filter.arguments {
+                for argument in arguments_expr.arguments {
+                    arguments.push(self.visit_value(scope, argument)?);
+                }
+            }
+            variable = self.apply_filter(filter.name, variable, arguments)?;
+        }
+        Ok(variable)
+    }
+
+    /// Apply the filter called `name`, whether it is used with a pipe or called as a function.
+    /// In strict mode, a `lookup` that finds no row and a `column` that is missing from a row
+    /// are errors
+    fn apply_filter(
+        &mut self,
+        name: InfoToken,
+        value: VarType,
+        arguments: Vec<VarType>,
+    ) -> Result<VarType, InterpreterError> {
+        if self.strict && name.slice == "column" {
+            if let Some((index, length)) = filters::missing_column(&value, &arguments) {
+                return Err(InterpreterError {
+                    msg: format!(
+                        "The index {} is out of bounds of a collection with {} items",
+                        index, length
+                    ),
+                    line_number: self.get_line_number_for_token(name),
+                });
+            }
+        }
+        let lookup = if self.strict && name.slice == "lookup" && arguments.len() == 2 {
+            Some((value_to_text(&arguments[0]), value_to_text(&arguments[1])))
+        } else {
+            None
+        };
+        match (filters::apply_filter(&name.slice, value, arguments), lookup) {
+            (Ok(VarType::Row(var)), Some((column, key))) if var.data.is_empty() => {
+                Err(InterpreterError {
+                    msg: format!("The lookup found no row with {} in column {}", key, column),
+                    line_number: self.get_line_number_for_token(name),
+                })
+            }
+            (Ok(value), _) => Ok(value),
+            (Err(e), _) => Err(InterpreterError {
+                msg: format!("{}", e),
+                line_number: self.get_line_number_for_token(name),
+            }),
+        }
+    }
+
+    /// Call a filter as a function, where the first argument is the value it is applied to
+    fn visit_function(
+        &mut self,
+        scope: &mut Scope,
+        function_expr: Box<FunctionExpr>,
+    ) -> Result<VarType, InterpreterError> {
+        let function_expr = *function_expr;
+        let name = function_expr.name;
+        let strict = self.strict;
+        if name.slice == "default" {
+            self.strict = false;
+        }
+        let mut arguments = vec![];
+        for argument in function_expr.arguments.arguments {
+            match self.visit_value(scope, argument) {
+                Ok(argument) => arguments.push(argument),
+                Err(error) => {
+                    self.strict = strict;
+                    return Err(error);
+                }
+            }
+        }
+        self.strict = strict;
+        if arguments.is_empty() {
+            return Err(InterpreterError {
+                msg: format!(
+                    "The {} function needs a value to work on, as in {}(value)",
+                    name.slice, name.slice
+                ),
+                line_number: self.get_line_number_for_token(name),
+            });
+        }
+        let value = arguments.remove(0);
+        self.apply_filter(name, value, arguments)
     }
 
     fn visit_accessor(
@@ -97,17 +645,260 @@ impl<'a> Interpreter<'a> {
         scope: &mut Scope,
         accessor_expr: AccessorExpr,
     ) -> Result<String, InterpreterError> {
+        let info_token = accessor_expr.variable.clone();
+        let variable = self.visit_accessor_value(scope, accessor_expr)?;
+        self.var_to_string(variable, info_token)
+    }
+
+    fn visit_accessor_value(
+        &mut self,
+        scope: &mut Scope,
+        accessor_expr: AccessorExpr,
+    ) -> Result<VarType, InterpreterError> {
         let mut variable = self.lookup(scope, accessor_expr.variable.clone())?.clone();
         for indexer in accessor_expr.indexes {
-            variable = self.visit_array_bracket(scope, indexer, variable)?;
+            variable = self.visit_index(scope, indexer, variable)?;
+        }
+        Ok(variable)
+    }
+
+    fn visit_if(
+        &mut self,
+        scope: &mut Scope,
+        if_expr: Box<IfExpr>,
+    ) -> Result<String, InterpreterError> {
+        let if_expr = *if_expr;
+        if self.visit_condition(scope, if_expr.if_start.condition)? {
+            return self.visit_expr(scope, *if_expr.block);
+        }
+
+        for else_if in if_expr.else_ifs {
+            if self.visit_condition(scope, else_if.condition)? {
+                return self.visit_expr(scope, *else_if.block);
+            }
+        }
+
+        match if_expr.r#else {
+            Some(r#else) => self.visit_expr(scope, *r#else.block),
+            None => Ok(String::from("")),
+        }
+    }
+
+    fn visit_condition(&mut self, scope: &mut Scope, expr: Expr) -> Result<bool, InterpreterError> {
+        let value = self.visit_value(scope, expr)?;
+        Ok(self.is_truthy(&value))
+    }
+
+    fn visit_binary(
+        &mut self,
+        scope: &mut Scope,
+        binary_expr: Box<BinaryExpr>,
+    ) -> Result<VarType, InterpreterError> {
+        let binary_expr = *binary_expr;
+        let operator = binary_expr.operator;
+        match operator.token {
+            Token::And => {
+                let result = self.visit_condition(scope, binary_expr.left)?
+                    && self.visit_condition(scope, binary_expr.right)?;
+                return Ok(VarType::Bool(Var::new(result)));
+            }
+            Token::Or => {
+                let result = self.visit_condition(scope, binary_expr.left)?
+                    || self.visit_condition(scope, binary_expr.right)?;
+                return Ok(VarType::Bool(Var::new(result)));
+            }
+            _ => (),
+        };
+
+        let left = self.visit_value(scope, binary_expr.left)?;
+        let right = self.visit_value(scope, binary_expr.right)?;
+        if let Token::Plus | Token::Minus | Token::Star | Token::Slash | Token::Percent =
+            operator.token
+        {
+            return self.visit_arithmetic(left, right, operator);
+        }
+        let result = match operator.token {
+            Token::Equal => self.equals(&left, &right),
+            Token::NotEqual => !self.equals(&left, &right),
+            _ => {
+                let ordering = match self.compare(&left, &right) {
+                    Some(ordering) => ordering,
+                    None => {
+                        return Err(InterpreterError {
+                            msg: format!("Cannot compare values using {}", operator.slice),
+                            line_number: self.get_line_number_for_token(operator),
+                        })
+                    }
+                };
+                match operator.token {
+                    Token::LessThan => ordering == Ordering::Less,
+                    Token::LessThanOrEqual => ordering != Ordering::Greater,
+                    Token::GreaterThan => ordering == Ordering::Greater,
+                    Token::GreaterThanOrEqual => ordering != Ordering::Less,
+                    _ => {
+                        return Err(InterpreterError {
+                            msg: format!("Unknown operator {}", operator.slice),
+                            line_number: self.get_line_number_for_token(operator),
+                        })
+                    }
+                }
+            }
+        };
+        Ok(VarType::Bool(Var::new(result)))
+    }
+
+    fn visit_arithmetic(
+        &mut self,
+        left: VarType,
+        right: VarType,
+        operator: InfoToken,
+    ) -> Result<VarType, InterpreterError> {
+        let (left, right) = match (as_integer(&left), as_integer(&right)) {
+            (Some(left), Some(right)) => (left, right),
+            _ => {
+                return Err(InterpreterError {
+                    msg: format!(
+                        "Cannot use {} on values that are not whole numbers",
+                        operator.slice
+                    ),
+                    line_number: self.get_line_number_for_token(operator),
+                })
+            }
+        };
+        let result = match operator.token {
+            Token::Plus => left.checked_add(right),
+            Token::Minus => left.checked_sub(right),
+            Token::Star => left.checked_mul(right),
+            Token::Slash => left.checked_div(right),
+            _ => left.checked_rem(right),
+        };
+        match result {
+            Some(result) => Ok(VarType::Number(Var::new(result))),
+            None => Err(InterpreterError {
+                msg: if right == 0 {
+                    String::from("Cannot divide by zero")
+                } else {
+                    format!("The result of {} is too large", operator.slice)
+                },
+                line_number: self.get_line_number_for_token(operator),
+            }),
+        }
+    }
+
+    fn visit_unary(
+        &mut self,
+        scope: &mut Scope,
+        unary_expr: Box<UnaryExpr>,
+    ) -> Result<VarType, InterpreterError> {
+        let unary_expr = *unary_expr;
+        match unary_expr.operator.token {
+            Token::Not => {
+                let result = !self.visit_condition(scope, unary_expr.operand)?;
+                Ok(VarType::Bool(Var::new(result)))
+            }
+            Token::Minus => {
+                let operand = self.visit_value(scope, unary_expr.operand)?;
+                let zero = VarType::Number(Var::new(0));
+                self.visit_arithmetic(zero, operand, unary_expr.operator)
+            }
+            _ => Err(InterpreterError {
+                msg: format!("Unknown operator {}", unary_expr.operator.slice),
+                line_number: self.get_line_number_for_token(unary_expr.operator),
+            }),
+        }
+    }
+
+    fn visit_is_empty(
+        &mut self,
+        scope: &mut Scope,
+        is_empty_expr: Box<IsEmptyExpr>,
+    ) -> Result<VarType, InterpreterError> {
+        let is_empty_expr = *is_empty_expr;
+        let is_empty = self.visit_value(scope, is_empty_expr.operand)?.is_empty();
+        let result = match is_empty_expr.not {
+            Some(_) => !is_empty,
+            None => is_empty,
+        };
+        Ok(VarType::Bool(Var::new(result)))
+    }
+
+    fn visit_literal(&mut self, literal_expr: LiteralExpr) -> Result<VarType, InterpreterError> {
+        let info_token = literal_expr.token;
+        match info_token.token {
+            Token::Number => Ok(VarType::Number(Var::new(
+                self.get_number_from_literal(info_token)?,
+            ))),
+            Token::StringLiteral => Ok(VarType::Value(Var::new(unescape_string_literal(
+                &info_token.slice,
+            )))),
+            _ => Err(InterpreterError {
+                msg: format!("Unexpected literal {}", info_token.slice),
+                line_number: self.get_line_number_for_token(info_token),
+            }),
+        }
+    }
+
+    fn is_truthy(&self, value: &VarType) -> bool {
+        match value {
+            VarType::Bool(var) => var.data,
+            VarType::Number(var) => var.data != 0,
+            _ => !value.is_empty(),
+        }
+    }
+
+    /// Check whether two values are the same. Text is compared exactly, even if it reads as a
+    /// number, so that keys such as `"007"` and `"1.10"` only match themselves
+    fn equals(&self, left: &VarType, right: &VarType) -> bool {
+        match (left, right) {
+            (VarType::Number(left), VarType::Number(right)) => left.data == right.data,
+            (VarType::Bool(left), VarType::Bool(right)) => left.data == right.data,
+            (VarType::Row(left), VarType::Row(right)) => left.data == right.data,
+            (VarType::Table(left), VarType::Table(right)) => left.data == right.data,
+            (VarType::Value(_), VarType::Value(_))
+            | (VarType::Value(_), VarType::Number(_))
+            | (VarType::Number(_), VarType::Value(_)) => {
+                value_to_text(left) == value_to_text(right)
+            }
+            _ => false,
+        }
+    }
+
+    /// Order two values, comparing them as numbers when both sides are numeric
+    fn compare(&self, left: &VarType, right: &VarType) -> Option<Ordering> {
+        if let (Some(left), Some(right)) = (as_number(left), as_number(right)) {
+            return left.partial_cmp(&right);
+        }
+        match (left, right) {
+            (VarType::Value(left), VarType::Value(right)) => Some(left.data.cmp(&right.data)),
+            (VarType::Value(left), VarType::Number(right)) => {
+                Some(left.data.cmp(&right.data.to_string()))
+            }
+            (VarType::Number(left), VarType::Value(right)) => {
+                Some(left.data.to_string().cmp(&right.data))
+            }
+            (VarType::Bool(left), VarType::Bool(right)) => Some(left.data.cmp(&right.data)),
+            (VarType::Row(left), VarType::Row(right)) if left.data == right.data => {
+                Some(Ordering::Equal)
+            }
+            (VarType::Table(left), VarType::Table(right)) if left.data == right.data => {
+                Some(Ordering::Equal)
+            }
+            _ => None,
         }
+    }
 
+    fn var_to_string(
+        &mut self,
+        variable: VarType,
+        info_token: InfoToken,
+    ) -> Result<String, InterpreterError> {
         match variable {
             VarType::Value(variable) => Ok(variable.data.to_string()),
             VarType::Number(variable) => Ok(variable.data.to_string()),
+            VarType::Bool(variable) => Ok(variable.data.to_string()),
             _ => Err(InterpreterError {
-                msg: format!("Cannot convert {} to String", accessor_expr.variable.slice),
-                line_number: self.get_line_number_for_token(accessor_expr.variable),
+                msg: format!("Cannot convert {} to String", info_token.slice),
+                line_number: self.get_line_number_for_token(info_token),
             }),
         }
     }
@@ -118,6 +909,10 @@ impl<'a> Interpreter<'a> {
         loop_expr: Box<LoopExpr>,
     ) -> Result<String, InterpreterError> {
         let mut strings: Vec<String> = vec![];
+        let separator = match &loop_expr.loop_start.separator {
+            Some(separator) => unescape_string_literal(&separator.value.slice),
+            None => String::from(""),
+        };
         let loop_iterator = self.visit_loop_start(scope, loop_expr.loop_start)?;
 
         for mut scope in loop_iterator {
@@ -125,7 +920,7 @@ impl<'a> Interpreter<'a> {
             strings.push(output);
         }
 
-        Ok(strings.join(" "))
+        Ok(strings.join(&separator))
     }
 
     fn visit_loop_start<'b>(
@@ -133,8 +928,30 @@ impl<'a> Interpreter<'a> {
         scope: &'b mut Scope,
         loop_start_expr: LoopStartExpr,
     ) -> Result<LoopIterator<'b>, InterpreterError> {
-        let (variable, min, max) =
-            self.visit_array_accessor(scope, loop_start_expr.array_accessor.clone())?;
+        let (variable, indexes) = match loop_start_expr.source {
+            LoopSourceExpr::Collection(array_accessor) => {
+                let info_token = array_accessor.variable.clone();
+                let (variable, indexes) = self.visit_array_accessor(scope, *array_accessor)?;
+                self.expect_iterable(&variable, info_token)?;
+                (variable, indexes)
+            }
+            LoopSourceExpr::Range(range_expr) => self.visit_range(scope, *range_expr)?,
+            LoopSourceExpr::Value(loop_value_expr) => {
+                let loop_value_expr = *loop_value_expr;
+                let variable = self.visit_filtered_value(
+                    scope,
+                    loop_value_expr.value,
+                    loop_value_expr.filters,
+                )?;
+                self.expect_iterable(&variable, loop_value_expr.token)?;
+                let length = match &variable {
+                    VarType::Table(var) => var.data.len(),
+                    VarType::Row(var) => var.data.len(),
+                    _ => 0,
+                };
+                (variable, (0..length).collect())
+            }
+        };
         let as_variable_name: Option<String> = match loop_start_expr.as_variable {
             Some(as_variable) => Some(as_variable.variable.slice),
             None => None,
@@ -153,105 +970,410 @@ impl<'a> Interpreter<'a> {
             None => None,
         };
 
-        match &variable {
-            VarType::Table(_var) => (),
-            VarType::Row(_var) => (),
-            _ => {
-                return Err(InterpreterError {
-                    msg: String::from("Attempt to loop on a non-iterable"),
-                    line_number: self
-                        .get_line_number_for_token(loop_start_expr.array_accessor.variable.clone()),
-                })
-            }
-        };
+        // The rows to join with are taken before the loop borrows the scope
+        let mut joins = vec![];
+        for join_expr in loop_start_expr.joins {
+            let (collection, indexes) =
+                self.visit_array_accessor(scope, join_expr.array_accessor.clone())?;
+            self.expect_iterable(&collection, join_expr.array_accessor.variable.clone())?;
+            let rows: Vec<VarType> = indexes
+                .into_iter()
+                .map(|index| loop_iterator::item_at(&collection, index))
+                .collect();
+            joins.push((join_expr, rows));
+        }
 
-        Ok(LoopIterator::new(
+        let mut loop_iterator = LoopIterator::new(
             scope,
             variable,
-            min,
-            max,
+            indexes,
             loop_variable_name,
             collection_variable_name,
             as_variable_name,
-        ))
-    }
-
-    fn visit_array_accessor(
+        );
+        for (join_expr, rows) in joins {
+            self.visit_join(&mut loop_iterator, join_expr, rows)?;
+        }
+        if let Some(where_clause) = loop_start_expr.where_clause {
+            let matches = loop_iterator
+                .map_scopes(|scope| self.visit_condition(scope, where_clause.condition.clone()))?;
+            let positions = (0..matches.len())
+                .filter(|&position| matches[position])
+                .collect();
+            loop_iterator.select(positions);
+        }
+        if let Some(distinct_expr) = loop_start_expr.distinct {
+            self.visit_distinct(&mut loop_iterator, distinct_expr)?;
+        }
+        if let Some(group_expr) = loop_start_expr.group {
+            self.visit_group(&mut loop_iterator, group_expr)?;
+        }
+        if let Some(sort_expr) = loop_start_expr.sort {
+            self.visit_sort(&mut loop_iterator, sort_expr)?;
+        }
+        if loop_start_expr.reverse.is_some() {
+            loop_iterator.reverse();
+        }
+        Ok(loop_iterator)
+    }
+
+    /// Evaluate a range of numbers, which is looped over as its first number along with how far
+    /// each number of the range is from it. The last number is not part of the range
+    fn visit_range(
+        &mut self,
+        scope: &mut Scope,
+        range_expr: RangeExpr,
+    ) -> Result<(VarType, Vec<usize>), InterpreterError> {
+        let start = self.visit_range_bound(scope, range_expr.start, range_expr.start_token)?;
+        let end = self.visit_range_bound(scope, range_expr.end, range_expr.end_token)?;
+        let length = usize::try_from(end.saturating_sub(start)).unwrap_or(0);
+        if length > MAX_RANGE_LENGTH {
+            return Err(InterpreterError {
+                msg: format!(
+                    "The range {}..{} has {} numbers, a range can have at most {}",
+                    start, end, length, MAX_RANGE_LENGTH
+                ),
+                line_number: self.get_line_number_for_token(range_expr.double_dot),
+            });
+        }
+        Ok((VarType::Number(Var::new(start)), (0..length).collect()))
+    }
+
+    fn visit_range_bound(
+        &mut self,
+        scope: &mut Scope,
+        bound: Expr,
+        info_token: InfoToken,
+    ) -> Result<i64, InterpreterError> {
+        let value = self.visit_value(scope, bound)?;
+        match as_integer(&value) {
+            Some(bound) => Ok(bound),
+            None => Err(InterpreterError {
+                msg: format!(
+                    "A range can only be bounded by whole numbers, found {}",
+                    info_token.slice
+                ),
+                line_number: self.get_line_number_for_token(info_token),
+            }),
+        }
+    }
+
+    fn expect_iterable(
+        &mut self,
+        variable: &VarType,
+        info_token: InfoToken,
+    ) -> Result<(), InterpreterError> {
+        match variable {
+            VarType::Table(_) | VarType::Row(_) => Ok(()),
+            _ => Err(InterpreterError {
+                msg: String::from("Attempt to loop on a non-iterable"),
+                line_number: self.get_line_number_for_token(info_token),
+            }),
+        }
+    }
+
+    /// Pair each item of a loop with the first of the given rows that matches the condition
+    /// of a `JOIN`. The row is named after the joined collection unless `AS` is used
+    fn visit_join(
+        &mut self,
+        loop_iterator: &mut LoopIterator,
+        join_expr: JoinExpr,
+        rows: Vec<VarType>,
+    ) -> Result<(), InterpreterError> {
+        let condition = join_expr.condition;
+        let variable_name = match join_expr.as_variable {
+            Some(as_variable) => as_variable.variable.slice,
+            None => join_expr.array_accessor.variable.slice,
+        };
+        let matches = loop_iterator.map_scopes(|scope| {
+            for row in &rows {
+                let mut row_scope = Scope::with_parent(scope);
+                row_scope.insert(variable_name.clone(), row.clone());
+                if self.visit_condition(&mut row_scope, condition.clone())? {
+                    return Ok(Some(row.clone()));
+                }
+            }
+            Ok(None)
+        })?;
+        if self.strict {
+            if let Some(position) = matches.iter().position(|row| row.is_none()) {
+                return Err(InterpreterError {
+                    msg: format!(
+                        "The JOIN found no row of {} for item {} of the loop",
+                        variable_name, position
+                    ),
+                    line_number: self.get_line_number_for_token(join_expr.join),
+                });
+            }
+        }
+        loop_iterator.join(variable_name, matches);
+        Ok(())
+    }
+
+    /// Skip the items of a loop that are the same as an item before them, or that have the
+    /// same key as one when `DISTINCT BY` is used
+    fn visit_distinct(
+        &mut self,
+        loop_iterator: &mut LoopIterator,
+        distinct_expr: DistinctExpr,
+    ) -> Result<(), InterpreterError> {
+        let by = match distinct_expr.by {
+            Some(by) => by,
+            None => {
+                loop_iterator.remove_repeated_items();
+                return Ok(());
+            }
+        };
+        let keys = loop_iterator.map_scopes(|scope| {
+            let key = self.visit_loop_key(scope, by.key.clone(), by.token.clone(), "distinct")?;
+            Ok(value_to_text(&key))
+        })?;
+        let mut seen = HashSet::new();
+        let positions = (0..keys.len())
+            .filter(|&position| seen.insert(&keys[position]))
+            .collect();
+        loop_iterator.select(positions);
+        Ok(())
+    }
+
+    /// Split the items of a loop into groups of items that have the same key, in the order
+    /// that each key is first seen
+    fn visit_group(
+        &mut self,
+        loop_iterator: &mut LoopIterator,
+        group_expr: GroupExpr,
+    ) -> Result<(), InterpreterError> {
+        let keys = loop_iterator.map_scopes(|scope| {
+            self.visit_loop_key(
+                scope,
+                group_expr.key.clone(),
+                group_expr.token.clone(),
+                "group",
+            )
+        })?;
+
+        let mut groups: Vec<(VarType, Vec<usize>)> = vec![];
+        let mut group_positions: HashMap<String, usize> = HashMap::new();
+        for (position, key) in keys.into_iter().enumerate() {
+            let text = value_to_text(&key);
+            match group_positions.get(&text) {
+                Some(&group_position) => groups[group_position].1.push(position),
+                None => {
+                    group_positions.insert(text, groups.len());
+                    groups.push((key, vec![position]));
+                }
+            }
+        }
+        let key_variable_name = match group_expr.key_variable {
+            Some(key_variable) => key_variable.slice,
+            None => format!("{}_key", group_expr.variable.slice),
+        };
+        loop_iterator.group(key_variable_name, group_expr.variable.slice, groups);
+        Ok(())
+    }
+
+    /// Evaluate a value that the items of a loop are grouped or sorted by
+    fn visit_loop_key(
+        &mut self,
+        scope: &mut Scope,
+        value: Expr,
+        info_token: InfoToken,
+        clause: &str,
+    ) -> Result<VarType, InterpreterError> {
+        let value = self.visit_value(scope, value)?;
+        if let VarType::Row(_) | VarType::Table(_) = value {
+            return Err(InterpreterError {
+                msg: format!(
+                    "Cannot {} by {}, it is a collection",
+                    clause, info_token.slice
+                ),
+                line_number: self.get_line_number_for_token(info_token),
+            });
+        }
+        Ok(value)
+    }
+
+    /// Order the items of a loop by the keys of a `SORT BY`. Items with the same keys keep
+    /// the order they had
+    fn visit_sort(
+        &mut self,
+        loop_iterator: &mut LoopIterator,
+        sort_expr: SortExpr,
+    ) -> Result<(), InterpreterError> {
+        let keys = loop_iterator.map_scopes(|scope| {
+            let mut values = vec![];
+            for key in &sort_expr.keys {
+                values.push(self.visit_loop_key(
+                    scope,
+                    key.value.clone(),
+                    key.token.clone(),
+                    "sort",
+                )?);
+            }
+            Ok(values)
+        })?;
+
+        let mut positions: Vec<usize> = (0..keys.len()).collect();
+        positions.sort_by(|&left, &right| {
+            for (index, key) in sort_expr.keys.iter().enumerate() {
+                let (left, right) = (&keys[left][index], &keys[right][index]);
+                let ordering = match key.ordering.as_ref().map(|ordering| &ordering.token) {
+                    Some(Token::Lexical) => value_to_text(left).cmp(&value_to_text(right)),
+                    Some(Token::Natural) => {
+                        compare_naturally(&value_to_text(left), &value_to_text(right))
+                    }
+                    _ => compare_numbers(left, right),
+                };
+                let ordering = match key.direction.as_ref().map(|direction| &direction.token) {
+                    Some(Token::Desc) => ordering.reverse(),
+                    _ => ordering,
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        loop_iterator.select(positions);
+        Ok(())
+    }
+
+    fn visit_array_accessor(
         &mut self,
         scope: &mut Scope,
         array_accessor_expr: ArrayAccessorExpr,
-    ) -> Result<(VarType, usize, usize), InterpreterError> {
+    ) -> Result<(VarType, Vec<usize>), InterpreterError> {
         let mut variable = self
             .lookup(scope, array_accessor_expr.variable.clone())?
             .clone();
         for indexer in array_accessor_expr.indexes {
-            variable = self.visit_array_bracket(scope, indexer, variable)?;
+            variable = self.visit_index(scope, indexer, variable)?;
         }
 
-        let (min, max) = self.visit_array_slice(
+        let indexes = self.visit_array_slice(
             scope,
             array_accessor_expr.variable,
             array_accessor_expr.array_slice,
             &variable,
         )?;
 
-        Ok((variable, min, max))
+        Ok((variable, indexes))
+    }
+
+    /// Take the part of a collection that is selected by a slice
+    fn visit_slice(
+        &mut self,
+        scope: &mut Scope,
+        array_accessor_expr: ArrayAccessorExpr,
+    ) -> Result<VarType, InterpreterError> {
+        let info_token = array_accessor_expr.variable.clone();
+        let (variable, indexes) = self.visit_array_accessor(scope, array_accessor_expr)?;
+        let slice = match &variable {
+            VarType::Table(var) => indexes
+                .iter()
+                .map(|&index| var.data.get(index).cloned())
+                .collect::<Option<Vec<_>>>()
+                .map(|rows| VarType::Table(Var::with_headers(rows, var.headers.clone()))),
+            VarType::Row(var) => indexes
+                .iter()
+                .map(|&index| var.data.get(index).cloned())
+                .collect::<Option<Vec<_>>>()
+                .map(|cells| {
+                    let headers = var.headers.as_ref().map(|headers| {
+                        indexes
+                            .iter()
+                            .map(|&index| headers.get(index).cloned().unwrap_or_default())
+                            .collect()
+                    });
+                    VarType::Row(Var::with_headers(cells, headers))
+                }),
+            _ => None,
+        };
+        match slice {
+            Some(slice) => Ok(slice),
+            None => Err(InterpreterError {
+                msg: format!("The slice of {} is out of bounds", info_token.slice),
+                line_number: self.get_line_number_for_token(info_token),
+            }),
+        }
     }
 
+    /// Find the indexes of the items of a collection that are selected by a slice,
+    /// counting negative bounds from the end and walking backwards for a negative step
     fn visit_array_slice(
         &mut self,
         scope: &mut Scope,
         variable_info_token: InfoToken,
         array_slice: Option<ArraySliceExpr>,
         collection: &VarType,
-    ) -> Result<(usize, usize), InterpreterError> {
-        let mut min_index = 0;
-        let mut _max_index = 0;
-        match array_slice {
-            Some(array_slice) => match collection {
-                VarType::Table(var) => {
-                    min_index = match array_slice.start_index.token.token {
-                        Token::DoubleDot => 0,
-                        _ => self.get_number_from_token(scope, array_slice.start_index.token)?,
-                    };
-                    _max_index = match array_slice.end_index.token.token {
-                        Token::DoubleDot => var.data.len(),
-                        _ => self.get_number_from_token(scope, array_slice.end_index.token)?,
-                    };
-                }
-                VarType::Row(var) => {
-                    min_index = match array_slice.start_index.token.token {
-                        Token::DoubleDot => 0,
-                        _ => self.get_number_from_token(scope, array_slice.start_index.token)?,
-                    };
-                    _max_index = match array_slice.end_index.token.token {
-                        Token::DoubleDot => var.data.len(),
-                        _ => self.get_number_from_token(scope, array_slice.end_index.token)?,
-                    };
-                }
-                _ => {
-                    return Err(InterpreterError {
-                        msg: String::from("Attempt to slice a non-iterable"),
-                        line_number: self.get_line_number_for_token(variable_info_token),
-                    })
-                }
-            },
-            None => match collection {
-                VarType::Table(var) => {
-                    _max_index = var.data.len();
-                }
-                VarType::Row(var) => {
-                    _max_index = var.data.len();
-                }
-                _ => {
-                    return Err(InterpreterError {
-                        msg: String::from("Attempt to loop on a non-iterable"),
-                        line_number: self.get_line_number_for_token(variable_info_token),
-                    })
-                }
-            },
+    ) -> Result<Vec<usize>, InterpreterError> {
+        let length = match collection {
+            VarType::Table(var) => var.data.len(),
+            VarType::Row(var) => var.data.len(),
+            _ => {
+                let msg = match array_slice {
+                    Some(_) => "Attempt to slice a non-iterable",
+                    None => "Attempt to loop on a non-iterable",
+                };
+                return Err(InterpreterError {
+                    msg: String::from(msg),
+                    line_number: self.get_line_number_for_token(variable_info_token),
+                });
+            }
+        };
+        let array_slice = match array_slice {
+            Some(array_slice) => array_slice,
+            None => return Ok((0..length).collect()),
         };
-        Ok((min_index, _max_index))
+
+        let step = match array_slice.step {
+            Some(step) => self.visit_array_slice_step(scope, step)?,
+            None => 1,
+        };
+        let (start, end) = if step > 0 {
+            (0, length as i64)
+        } else {
+            (length as i64 - 1, -1)
+        };
+        let start_token = array_slice.start_index.token.clone();
+        let end_token = array_slice.end_index.token.clone();
+        let start = self.visit_array_slice_index(scope, array_slice.start_index, start, length)?;
+        let end = self.visit_array_slice_index(scope, array_slice.end_index, end, length)?;
+
+        // Walking forwards, the bounds can go up to the length. Walking backwards, they
+        // can go down to -1, which is right before the first item
+        let limits = if step > 0 {
+            (0, length as i64)
+        } else {
+            (-1, length as i64 - 1)
+        };
+        let name = variable_info_token.slice;
+        let start = self.check_slice_bound(start, limits, &name, length, start_token)?;
+        let end = self.check_slice_bound(end, limits, &name, length, end_token)?;
+
+        let mut indexes = vec![];
+        let mut index = start;
+        while (step > 0 && index < end) || (step < 0 && index > end) {
+            indexes.push(index as usize);
+            // A step that goes past the end of the numbers also goes past the end of the slice
+            index = match index.checked_add(step) {
+                Some(index) => index,
+                None => break,
+            };
+        }
+        Ok(indexes)
+    }
+
+    fn visit_index(
+        &mut self,
+        scope: &mut Scope,
+        index_expr: IndexExpr,
+        collection: VarType,
+    ) -> Result<VarType, InterpreterError> {
+        match index_expr {
+            IndexExpr::ArrayBracket(node) => self.visit_array_bracket(scope, node, collection),
+            IndexExpr::DotVariable(node) => self.visit_column_name(node.variable, collection),
+        }
     }
 
     fn visit_array_bracket(
@@ -260,24 +1382,94 @@ impl<'a> Interpreter<'a> {
         array_bracket_expr: ArrayBracketExpr,
         collection: VarType,
     ) -> Result<VarType, InterpreterError> {
-        let index = self.visit_array_bracket_index(scope, array_bracket_expr.clone().variable)?;
-        match collection {
+        if array_bracket_expr.variable.token.token == Token::StringLiteral {
+            let mut info_token = array_bracket_expr.variable.token;
+            info_token.slice = unescape_string_literal(&info_token.slice);
+            return self.visit_column_name(info_token, collection);
+        }
+
+        let info_token = array_bracket_expr.variable.token.clone();
+        let length = match &collection {
+            VarType::Table(var) => var.data.len(),
+            VarType::Row(var) => var.data.len(),
+            _ => {
+                return Err(InterpreterError {
+                    msg: String::from("Attempt to index a non-iterable"),
+                    line_number: self.get_line_number_for_token(info_token),
+                })
+            }
+        };
+        let number = self.visit_array_bracket_index(scope, array_bracket_expr.variable)?;
+        let index = usize::try_from(resolve_index(number, length))
+            .ok()
+            .filter(|&index| index < length);
+        if self.strict && index.is_none() {
+            return Err(InterpreterError {
+                msg: format!(
+                    "The index {} is out of bounds of a collection with {} items",
+                    number, length
+                ),
+                line_number: self.get_line_number_for_token(info_token),
+            });
+        }
+        Ok(match collection {
             VarType::Table(var) => {
-                let mut value = var.data.get(index);
-                let otherwise_value: &Vec<String> = &vec![];
-                let value = value.get_or_insert(otherwise_value);
-                Ok(VarType::Row(Var::new(value.clone())))
+                let value = index.map(|index| var.data[index].clone());
+                VarType::Row(Var::with_headers(
+                    value.unwrap_or_default(),
+                    var.headers.clone(),
+                ))
             }
             VarType::Row(var) => {
-                let mut value = var.data.get(index);
-                let otherwise_value = &String::from("");
-                let value = value.get_or_insert(otherwise_value);
-                Ok(VarType::Value(Var::new(value.clone())))
+                let value = index.map(|index| var.data[index].clone());
+                VarType::Value(Var::new(value.unwrap_or_default()))
             }
-            _ => Err(InterpreterError {
-                msg: String::from("Attempt to index a non-iterable"),
-                line_number: self
-                    .get_line_number_for_token(array_bracket_expr.variable.token.clone()),
+            _ => unreachable!("Only collections have a length"),
+        })
+    }
+
+    /// Access a column of a row using the name it has in the headers of its collection
+    fn visit_column_name(
+        &mut self,
+        info_token: InfoToken,
+        collection: VarType,
+    ) -> Result<VarType, InterpreterError> {
+        let var = match collection {
+            VarType::Row(var) => var,
+            _ => {
+                return Err(InterpreterError {
+                    msg: format!("Cannot access the column {} of a non-row", info_token.slice),
+                    line_number: self.get_line_number_for_token(info_token),
+                })
+            }
+        };
+        if var.headers.is_none() {
+            return Err(InterpreterError {
+                msg: format!(
+                    "Cannot access the column {} by name, the collection was not imported with \"headers: true;\"",
+                    info_token.slice
+                ),
+                line_number: self.get_line_number_for_token(info_token),
+            });
+        }
+
+        match var.column_index(&info_token.slice) {
+            Some(index) if self.strict && index >= var.data.len() => Err(InterpreterError {
+                msg: format!(
+                    "The column {} is at index {}, which is out of bounds of a row with {} items",
+                    info_token.slice,
+                    index,
+                    var.data.len()
+                ),
+                line_number: self.get_line_number_for_token(info_token),
+            }),
+            Some(index) => {
+                let value = var.data.get(index).cloned().unwrap_or_default();
+                Ok(VarType::Value(Var::new(value)))
+            }
+            None => Err(InterpreterError {
+                msg: format!("Unknown column {}", info_token.slice),
+                line_number: self.get_line_number_for_token(info_token),
             }),
         }
     }
@@ -286,40 +1478,110 @@ impl<'a> Interpreter<'a> {
         &mut self,
         scope: &mut Scope,
         array_bracket_index_expr: ArrayBracketIndexExpr,
-    ) -> Result<usize, InterpreterError> {
-        self.get_number_from_token(scope, array_bracket_index_expr.token)
+    ) -> Result<i64, InterpreterError> {
+        self.get_number_from_expr(
+            scope,
+            array_bracket_index_expr.index,
+            array_bracket_index_expr.token,
+        )
     }
 
-    fn get_number_from_token(
+    /// Evaluate the bound of a slice, using `otherwise` for `..`
+    fn visit_array_slice_index(
         &mut self,
         scope: &mut Scope,
-        info_token: InfoToken,
-    ) -> Result<usize, InterpreterError> {
-        match info_token.token {
-            Token::Number => match info_token.slice.parse::<usize>() {
-                Err(_) => Err(InterpreterError {
-                    msg: format!("Cannot index using the variable {}", info_token.slice),
-                    line_number: self.get_line_number_for_token(info_token),
-                }),
-                Ok(val) => Ok(val),
-            },
-            Token::Variable => {
-                if let VarType::Number(x) = self.lookup(scope, info_token.clone())? {
-                    Ok(x.data)
-                } else {
-                    return Err(InterpreterError {
-                        msg: format!("Cannot index using the variable {}", info_token.slice),
-                        line_number: self.get_line_number_for_token(info_token),
-                    });
-                }
+        array_slice_index_expr: ArraySliceIndexExpr,
+        otherwise: i64,
+        length: usize,
+    ) -> Result<i64, InterpreterError> {
+        match array_slice_index_expr.index {
+            Some(index) => {
+                self.get_index_from_expr(scope, index, array_slice_index_expr.token, length)
             }
-            _ => Err(InterpreterError {
-                msg: format!("Cannot index arrays using {}", info_token.slice),
+            None => Ok(otherwise),
+        }
+    }
+
+    /// Make sure that the bound of a slice is within the `(lowest, highest)` limits, or move
+    /// it there if the file uses the clamp mode
+    fn check_slice_bound(
+        &mut self,
+        bound: i64,
+        (lowest, highest): (i64, i64),
+        collection_name: &str,
+        length: usize,
+        info_token: InfoToken,
+    ) -> Result<i64, InterpreterError> {
+        if self.clamp || (lowest..=highest).contains(&bound) {
+            return Ok(bound.max(lowest).min(highest));
+        }
+        Err(InterpreterError {
+            msg: format!(
+                "The slice of {} is out of bounds at {}, it has {} items",
+                collection_name, bound, length
+            ),
+            line_number: self.get_line_number_for_token(info_token),
+        })
+    }
+
+    /// Evaluate the step of a slice, where `..` steps forward one item at a time
+    fn visit_array_slice_step(
+        &mut self,
+        scope: &mut Scope,
+        array_slice_step_expr: ArraySliceStepExpr,
+    ) -> Result<i64, InterpreterError> {
+        let info_token = array_slice_step_expr.step_index.token;
+        let step = match array_slice_step_expr.step_index.index {
+            Some(step) => self.get_number_from_expr(scope, step, info_token.clone())?,
+            None => 1,
+        };
+        match step {
+            0 => Err(InterpreterError {
+                msg: String::from("The step of a slice cannot be zero"),
+                line_number: self.get_line_number_for_token(info_token),
+            }),
+            step => Ok(step),
+        }
+    }
+
+    /// Evaluate an index, counting negative indexes from the end of a collection of the given length
+    fn get_index_from_expr(
+        &mut self,
+        scope: &mut Scope,
+        index: Expr,
+        info_token: InfoToken,
+        length: usize,
+    ) -> Result<i64, InterpreterError> {
+        let index = self.get_number_from_expr(scope, index, info_token)?;
+        Ok(resolve_index(index, length))
+    }
+
+    fn get_number_from_expr(
+        &mut self,
+        scope: &mut Scope,
+        expr: Expr,
+        info_token: InfoToken,
+    ) -> Result<i64, InterpreterError> {
+        let value = self.visit_value(scope, expr)?;
+        match as_integer(&value) {
+            Some(number) => Ok(number),
+            None => Err(InterpreterError {
+                msg: format!("Cannot index using {}", info_token.slice),
                 line_number: self.get_line_number_for_token(info_token),
             }),
         }
     }
 
+    fn get_number_from_literal(&mut self, info_token: InfoToken) -> Result<i64, InterpreterError> {
+        match info_token.slice.parse::<i64>() {
+            Err(_) => Err(InterpreterError {
+                msg: format!("Invalid number {}", info_token.slice),
+                line_number: self.get_line_number_for_token(info_token),
+            }),
+            Ok(val) => Ok(val),
+        }
+    }
+
     fn lookup<'b>(
         &mut self,
         scope: &'b mut Scope,
@@ -339,6 +1601,117 @@ impl<'a> Interpreter<'a> {
     }
 }
 
+/// Count a negative index from the end of a collection of the given length
+fn resolve_index(index: i64, length: usize) -> i64 {
+    if index < 0 {
+        index + length as i64
+    } else {
+        index
+    }
+}
+
+/// Read a value as a whole number if it holds one
+fn as_integer(value: &VarType) -> Option<i64> {
+    match value {
+        VarType::Number(var) => Some(var.data),
+        VarType::Value(var) => var.data.trim().parse::<i64>().ok(),
+        _ => None,
+    }
+}
+
+/// Read a value as a number if it holds one. Text such as `nan` or `inf` is not a number
+fn as_number(value: &VarType) -> Option<f64> {
+    match value {
+        VarType::Number(var) => Some(var.data as f64),
+        VarType::Value(var) => var
+            .data
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite()),
+        _ => None,
+    }
+}
+
+/// The text of a value that is not a collection
+fn value_to_text(value: &VarType) -> String {
+    match value {
+        VarType::Value(var) => var.data.clone(),
+        VarType::Number(var) => var.data.to_string(),
+        VarType::Bool(var) => var.data.to_string(),
+        VarType::Row(_) | VarType::Table(_) => String::from(""),
+    }
+}
+
+/// Compare values as numbers, putting the values that are not numbers after the ones that
+/// are, in the order of their text
+fn compare_numbers(left: &VarType, right: &VarType) -> Ordering {
+    match (as_number(left), as_number(right)) {
+        (Some(left), Some(right)) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => value_to_text(left).cmp(&value_to_text(right)),
+    }
+}
+
+/// Compare text so that the numbers in it are ordered by their value, as in
+/// `item2` coming before `item10`
+fn compare_naturally(left: &str, right: &str) -> Ordering {
+    let mut left = left.chars().peekable();
+    let mut right = right.chars().peekable();
+    loop {
+        let ordering = match (left.peek(), right.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let l = take_digits(&mut left);
+                let r = take_digits(&mut right);
+                let (l, r) = (l.trim_start_matches('0'), r.trim_start_matches('0'));
+                l.len().cmp(&r.len()).then_with(|| l.cmp(r))
+            }
+            (Some(l), Some(r)) => {
+                let ordering = l.cmp(r);
+                left.next();
+                right.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+    digits
+}
+
+/// Strip the surrounding quotes of a string literal and resolve its escape sequences
+fn unescape_string_literal(literal: &str) -> String {
+    let mut result = String::new();
+    let mut chars = literal[1..literal.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
 pub struct InterpreterError {
     pub msg: String,
     line_number: usize,
@@ -387,3 +1760,561 @@ impl From<InterpreterError> for GeneralError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A directory of files for a test, which is unique to the test and removed when it ends
+    struct TestDirectory {
+        path: PathBuf,
+    }
+
+    impl TestDirectory {
+        fn new() -> TestDirectory {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let count = COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let path = std::env::temp_dir().join(format!(
+                "hamster_wheel_test_{}_{}",
+                std::process::id(),
+                count
+            ));
+            // A directory left over by an earlier run with the same process id is replaced
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TestDirectory { path }
+        }
+
+        fn write(&self, file: &str, content: &str) {
+            let path = self.path.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn render(text: &str) -> String {
+        render_with_table(text, &[&["key", "en", "ar"], &["title", "Title", ""]])
+    }
+
+    fn render_with_table(text: &str, rows: &[&[&str]]) -> String {
+        let mut importer = Importer::new(PathBuf::from("."));
+        let mut scope = Scope::new();
+        let rows = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect();
+        scope.insert(String::from("table"), VarType::Table(Var::new(rows)));
+        match Interpreter::new(text, &mut importer).interpret(&mut scope) {
+            Ok(result) => result.text,
+            Err(e) => e.msg,
+        }
+    }
+
+    #[test]
+    fn if_else_if_else() {
+        let template = "{{ OUTPUT out }}{{ LOOP table[1, ..] as row }}{{ IF row[2] is not empty }}ar{{ ELSE IF row[1] == \"Title\" and not row[0] is empty }}en{{ ELSE }}none{{ END }}{{ END }}";
+        assert_eq!(render(template), "en");
+    }
+
+    #[test]
+    fn else_and_end_must_close_a_block() {
+        let template = "{{ OUTPUT out }}{{ IF 1 }}a{{ END }}\n{{ ELSE }}after";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nUnexpected ELSE, there is no LOOP, IF or other block for it to close"
+        );
+        let template = "{{ OUTPUT out }}{{ LOOP table[0] as cell }}{{ END }}{{ end }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nUnexpected END, there is no LOOP, IF or other block for it to close"
+        );
+    }
+
+    #[test]
+    fn variables_set_in_a_file_are_not_seen_by_other_files() {
+        let mut importer = Importer::new(PathBuf::from("."));
+        let mut scope = Scope::new();
+        let template = "{{ OUTPUT a }}{{ SET title = \"a\" }}{{ title }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "a");
+        let template = "{{ OUTPUT b }}{{ title }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(
+            result.err().unwrap().msg,
+            "Error at line number 1\nAttempted to use undeclared variable title"
+        );
+    }
+
+    #[test]
+    fn if_compares_numbers() {
+        let template = "{{ OUTPUT out }}{{ LOOP(i) table[0] }}{{ IF i > 0 or (i < 1 and i != 0) }}{{ i }}{{ END }}{{ END }}";
+        assert_eq!(render(template), "12");
+    }
+
+    #[test]
+    fn filters_are_chained_left_to_right() {
+        let template = "{{ OUTPUT out }}{{ table[1][0] | upper | pad_left(7, \"-\") | replace(\"T\", \"t\") }}";
+        assert_eq!(render(template), "--tItLE");
+        let template = "{{ OUTPUT out }}{{ table[1][2] | default(table[1][1]) | length }}";
+        assert_eq!(render(template), "5");
+    }
+
+    #[test]
+    fn text_is_written_as_it_is() {
+        let template =
+            "{{ OUTPUT out }}\n  {{ LOOP table[0] as cell }}\n    {{ cell }} {{- END }}\n  ";
+        assert_eq!(render(template), "\n  \n    key\n    en\n    ar\n  ");
+    }
+
+    #[test]
+    fn keywords_can_be_used_as_names() {
+        let template = "{{ OUTPUT out }}{{ SET set = \"s\" }}{{ SET not = 1 }}{{ LOOP table[0] as on WHERE on != \"en\" SORT BY on desc }}{{ on }}{{ set }}{{ IF not not }}!{{ END }}{{ END }};{{ set | upper }}{{ not + 1 }}";
+        assert_eq!(render(template), "keysars;S2");
+        let template = "{{ OUTPUT out }}{{ LOOP table[1, ..] as row JOIN table[0, 1] AS join ON join[0] != row[0] }}{{ join[1] }}{{ END }}{{ LOOP table[0, 1] as by SORT BY by[0] asc }}{{ by[0] }}{{ END }}";
+        assert_eq!(render(template), "enkey");
+    }
+
+    #[test]
+    fn loop_separator_and_metadata() {
+        let template = "{{ OUTPUT out }}{{ LOOP table[0] as cell SEPARATOR \", \" }}{{ IF loop.first }}[{{ END }}{{ cell }}:{{ loop.length }}:{{ loop.remaining }}{{ IF loop.last }}]{{ END }}{{ END }}";
+        assert_eq!(render(template), "[key:3:2, en:3:1, ar:3:0]");
+        let template = "{{ OUTPUT out }}{{ SET length = 5 }}{{ LOOP table[0] as cell }}{{ length }}{{ loop.index }}{{ loop.collection_index }}{{ END }}";
+        assert_eq!(render(template), "500511522");
+    }
+
+    #[test]
+    fn columns_are_accessed_by_header_name() {
+        let mut importer = Importer::new(PathBuf::from("."));
+        let mut scope = Scope::new();
+        scope.insert(
+            String::from("table"),
+            VarType::Table(Var::with_headers(
+                vec![vec![String::from("title"), String::from("Title")]],
+                Some(vec![String::from("key"), String::from("end")]),
+            )),
+        );
+        let template =
+            "{{ OUTPUT out }}{{ LOOP table as row }}{{ row.key }}={{ row[\"end\"] }}{{ row.end }}{{ END }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "title=TitleTitle");
+    }
+
+    #[test]
+    fn header_names_can_be_written_in_any_language() {
+        let directory = TestDirectory::new();
+        directory.write("words.csv", "key,é,عنوان\ntitle,Titre,عنوان رئيسي\n");
+
+        let mut importer = Importer::new(directory.path.clone());
+        let mut scope = Scope::new();
+        let template = "{{ OUTPUT out }}{{ IMPORT }}name: words; path: words.csv; headers: true;{{ END }}{{ LOOP words as row }}{{ row.key }}={{ row.é }}/{{ row.عنوان }}{{ END }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "title=Titre/عنوان رئيسي");
+    }
+
+    #[test]
+    fn default_escaper_is_skipped_by_raw() {
+        let template = "{{ OUTPUT out }}{{ ESCAPE html }}{{ table[0][0] | replace(\"k\", \"<b>\") }}-{{ table[0][0] | replace(\"k\", \"<b>\") | raw }}";
+        assert_eq!(render(template), "&lt;b&gt;ey-<b>ey");
+    }
+
+    #[test]
+    fn comments_produce_no_output() {
+        let template = "{{# Explains the file #}}\n{{ OUTPUT out }}{{ LOOP table[0] as cell }}{{# {{ cell }}\n #}}{{ cell }}{{ END }}{{-# -#}} !";
+        assert_eq!(render(template), "keyenar!");
+        let template = "{{ OUTPUT out }}\n{{# never closed }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nThe comment opened with {{# is never closed"
+        );
+    }
+
+    #[test]
+    fn raw_blocks_and_literal_mustaches() {
+        let template = "{{ OUTPUT out }}{{ RAW }}<p>{{ $t(\"title\") }}</p>{{ if .Ok }}\\{{ end }}{{ ENDRAW }} \\{{ table[0][0] | raw }}}";
+        assert_eq!(
+            render(template),
+            "<p>{{ $t(\"title\") }}</p>{{ if .Ok }}\\{{ end }} {{ table[0][0] | raw }}}"
+        );
+        let template = "{{ OUTPUT out }}\\{{{{ table[0][0] | raw }}}}";
+        assert_eq!(render(template), "{{key}}");
+    }
+
+    #[test]
+    fn delimiters_are_changed_for_the_rest_of_the_file() {
+        let template = "{{ OUTPUT out }}{{ DELIMITERS <% %> }}{{ <% table[0][0] %> }} <%# {{ #%><% RAW %><% ENDRAW %><%- DELIMITERS [[ ]] -%> [[ table[0][1] ]]";
+        assert_eq!(render(template), "{{ key }} en");
+        let template = "{{ OUTPUT out }}\n{{ DELIMITERS <% }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nDelimiters must be in the form of: \"DELIMITERS left right\", for example \"DELIMITERS <% %>\""
+        );
+    }
+
+    #[test]
+    fn included_files_are_rendered_in_the_current_scope() {
+        let directory = TestDirectory::new();
+        directory.write(
+            "partials/cell.hamster_wheel",
+            "<{{ cell }}>{{ INCLUDE ../end.hamster_wheel }}",
+        );
+        directory.write("end.hamster_wheel", ";");
+        directory.write("cycle.hamster_wheel", "{{ INCLUDE cycle.hamster_wheel }}");
+
+        let mut importer = Importer::new(directory.path.clone());
+        let mut scope = Scope::new();
+        scope.insert(
+            String::from("cell"),
+            VarType::Value(Var::new(String::from("a"))),
+        );
+        let template = "{{ OUTPUT out }}{{ INCLUDE partials/cell.hamster_wheel }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "<a>;");
+
+        let template = "{{ OUTPUT out }}\n{{ INCLUDE cycle.hamster_wheel }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        let error = result.err().unwrap().msg;
+        assert!(error.starts_with("Error at line number 2\nFailed to include cycle.hamster_wheel"));
+        assert!(error.contains("Include cycle detected"));
+    }
+
+    #[test]
+    fn macros_bind_their_arguments() {
+        let template = "{{ OUTPUT out }}{{ DEFINE pair(left, right) }}{{ left }}={{ right | upper }};{{ END }}{{ LOOP table[1, ..] as row }}{{ CALL pair(row[0], \"x\") }}{{ CALL pair(row[1], row[0]) }}{{ END }}";
+        assert_eq!(render(template), "title=X;Title=TITLE;");
+        let template = "{{ OUTPUT out }}{{ DEFINE pair(left, right) }}{{ END }}{{ CALL pair(1) }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nThe macro pair expects 2 arguments, found 1"
+        );
+        let template = "{{ OUTPUT out }}{{ CALL later() }}{{ DEFINE later() }}x{{ DEFINE inner() }}y{{ END }}{{ END }}{{ CALL inner() }}";
+        assert_eq!(render(template), "xy");
+        let template =
+            "{{ OUTPUT out }}{{ DEFINE again() }}{{ CALL again() }}{{ END }}{{ CALL again() }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nFailed to call again\nError at line number 1\nThe macro again cannot call itself"
+        );
+    }
+
+    #[test]
+    fn macros_can_be_defined_in_included_files() {
+        let directory = TestDirectory::new();
+        directory.write(
+            "macros.hamster_wheel",
+            "{{ DEFINE quote(value) }}\"{{ value }}\"{{ END }}",
+        );
+
+        let mut importer = Importer::new(directory.path.clone());
+        let mut scope = Scope::new();
+        let template = "{{ OUTPUT out }}{{ INCLUDE macros.hamster_wheel }}{{ CALL quote(\"a\") }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "\"a\"");
+    }
+
+    #[test]
+    fn extended_files_render_the_blocks_that_are_overridden() {
+        let directory = TestDirectory::new();
+        directory.write(
+            "base.hamster_wheel",
+            "// header\n{{ BLOCK body }}body{{ END }}\n{{ BLOCK footer }}// footer{{ END }}",
+        );
+        directory.write(
+            "layout.hamster_wheel",
+            "{{ EXTENDS base.hamster_wheel }}{{ BLOCK footer }}// {{ name }}{{ END }}",
+        );
+
+        let mut importer = Importer::new(directory.path.clone());
+        let mut scope = Scope::new();
+        scope.insert(
+            String::from("name"),
+            VarType::Value(Var::new(String::from("end"))),
+        );
+        let template = "{{ OUTPUT out }}{{ EXTENDS base.hamster_wheel }}ignored{{ BLOCK body }}{{ name | upper }}{{ END }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "// header\nEND\n// footer");
+
+        let template =
+            "{{ OUTPUT out }}{{ EXTENDS layout.hamster_wheel }}{{ BLOCK footer }}//{{ END }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "// header\nbody\n//");
+        let template = "{{ OUTPUT out }}{{ EXTENDS layout.hamster_wheel }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(result.unwrap().text, "// header\nbody\n// end");
+        let template = "{{ OUTPUT out }}\n{{ IF name }}{{ EXTENDS base.hamster_wheel }}{{ END }}";
+        let result = Interpreter::new(template, &mut importer).interpret(&mut scope);
+        assert_eq!(
+            result.err().unwrap().msg,
+            "Error at line number 2\nEXTENDS can only be used once, at the top level of a file"
+        );
+    }
+
+    #[test]
+    fn set_binds_values_in_the_current_block() {
+        let template = "{{ OUTPUT out }}{{ SET header = table[0] }}{{ SET rows = table[1, ..] }}{{ LOOP rows as row }}{{ SET title = row[1] | upper }}{{ header[0] }}={{ title }}{{ END }}{{ SET languages = header[1, ..] }}{{ LOOP languages as language }};{{ language }}{{ END }}";
+        assert_eq!(render(template), "key=TITLE;en;ar");
+        let template =
+            "{{ OUTPUT out }}{{ LOOP table[0] as cell }}{{ SET last = cell }}{{ END }}{{ last }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nAttempted to use undeclared variable last"
+        );
+    }
+
+    #[test]
+    fn arithmetic_in_indexes_slices_and_output() {
+        let template = "{{ OUTPUT out }}{{ LOOP(i) table[0][.., 3 - 1] as cell }}{{ i + 1 }}.{{ cell }}={{ table[1][i * 2 % 3] }} {{ END }}";
+        assert_eq!(render(template), "1.key=title 2.en= ");
+        let template =
+            "{{ OUTPUT out }}{{ 2 + 3 * (4 - 1) / 2 }} {{ -7 / 2 }} {{ 7 % -3 }} {{ \"4\" - 1 }}";
+        assert_eq!(render(template), "6 -3 1 3");
+        let template = "{{ OUTPUT out }}a {{-1}} {{- -1 }}";
+        assert_eq!(render(template), "a -1-1");
+        let template = "{{ OUTPUT out }}{{ 1 / (1 - 1) }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nCannot divide by zero"
+        );
+        let template = "{{ OUTPUT out }}{{ table[0][0] + 1 }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nCannot use + on values that are not whole numbers"
+        );
+    }
+
+    #[test]
+    fn negative_indexes_and_stepped_slices() {
+        let template = "{{ OUTPUT out }}{{ table[0][-1] }} {{ table[-1][-3] }} {{ table[0][-4] }}.";
+        assert_eq!(render(template), "ar title .");
+        let template = "{{ OUTPUT out }}{{ LOOP(i, j) table[0][.., .., -1] as cell }}{{ i }}{{ j }}{{ cell }} {{ END }}";
+        assert_eq!(render(template), "02ar 11en 20key ");
+        let template = "{{ OUTPUT out }}{{ LOOP table[0][-3, .., 2] as cell }}{{ cell }} {{ END }}{{ LOOP table[0][-1, 0, -1] as cell }}{{ cell }} {{ END }}";
+        assert_eq!(render(template), "key ar ar en ");
+        let template = "{{ OUTPUT out }}{{ LOOP table[0][.., .., 1 - 1] as cell }}{{ END }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nThe step of a slice cannot be zero"
+        );
+    }
+
+    #[test]
+    fn slices_out_of_bounds_are_errors_unless_clamped() {
+        let template = "{{ OUTPUT out }}\n{{ LOOP table[0, 100] as row }}{{ END }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nThe slice of table is out of bounds at 100, it has 2 items"
+        );
+        let template = "{{ OUTPUT out }}{{ SET cells = table[0][-4, ..] }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nThe slice of table is out of bounds at -1, it has 3 items"
+        );
+        let template = "{{ OUTPUT out }}{{ MODE clamp }}{{ LOOP table[0][1, 100] as cell }}{{ cell }} {{ END }}{{ LOOP table[0][100, -10, -1] as cell }}{{ cell }} {{ END }}";
+        assert_eq!(render(template), "en ar ar en key ");
+        let template = "{{ OUTPUT out }}{{ LOOP table[0][1, .., 9223372036854775807] as cell }}{{ cell }} {{ END }}{{ LOOP table[0][.., .., -9223372036854775807] as cell }}{{ cell }} {{ END }}";
+        assert_eq!(render(template), "en ar ");
+        let template = "{{ OUTPUT out }}{{ MODE loose }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nUnknown mode loose"
+        );
+    }
+
+    #[test]
+    fn strict_mode_reports_missing_cells_and_rows() {
+        let template = "{{ OUTPUT out }}{{ table[0][3] }}{{ table[2][0] }}.";
+        assert_eq!(render(template), ".");
+        let template = "{{ OUTPUT out }}{{ MODE strict }}\n{{ table[0][-1] }} {{ table[0][3] }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nThe index 3 is out of bounds of a collection with 3 items"
+        );
+        let template = "{{ OUTPUT out }}{{ MODE strict }}{{ SET row = table[-3] }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nThe index -3 is out of bounds of a collection with 2 items"
+        );
+        let template = "{{ OUTPUT out }}{{ MODE strict }}{{ table[1][2] }}|{{ table[1][5] | default(\"none\") }}|{{ SET row = table[9] | default(\"-\") }}{{ row }}";
+        assert_eq!(render(template), "|none|-");
+        let template = "{{ OUTPUT out }}{{ MODE strict }}{{ lookup(table, 0, \"title\") | length }}{{ SET row = lookup(table, 0, \"x\") | default(\"-\") }}{{ row }}{{ default(lookup(table, 1, \"x\"), \"-\") }}";
+        assert_eq!(render(template), "3--");
+        let template =
+            "{{ OUTPUT out }}{{ MODE strict }}\n{{ SET row = table | lookup(0, \"x\") }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nThe lookup found no row with x in column 0"
+        );
+        let template = "{{ OUTPUT out }}{{ MODE strict }}{{ table | column(2) | length }}{{ table | column(3) | default(\"-\") | length }}\n{{ table | column(3) | length }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nThe index 3 is out of bounds of a collection with 3 items"
+        );
+        let template = "{{ OUTPUT out }}{{ MODE strict }}\n{{ LOOP table as row JOIN table AS other ON other[1] == row[0] }}{{ END }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nThe JOIN found no row of other for item 0 of the loop"
+        );
+    }
+
+    #[test]
+    fn loops_only_yield_the_items_that_match_where() {
+        let template = "{{ OUTPUT out }}{{ LOOP(i, j) table[0] as cell WHERE cell != \"en\" SEPARATOR \",\" }}{{ i }}{{ j }}{{ cell }}{{ IF loop.last }}.{{ END }}{{ END }}";
+        assert_eq!(render(template), "00key,12ar.");
+        let template = "{{ OUTPUT out }}{{ LOOP table[1, ..] as row WHERE row[2] is not empty }}{{ row[0] }}{{ END }}.";
+        assert_eq!(render(template), ".");
+        let rows: &[&[&str]] = &[&["1.10", "1.1", "nan", "007", "7"]];
+        let template = "{{ OUTPUT out }}{{ LOOP table[0] as cell WHERE cell == \"1.1\" or cell == \"nan\" or cell == 7 }}{{ cell }} {{ END }}{{ table[0][0] < table[0][1] }}";
+        assert_eq!(render_with_table(template, rows), "1.1 nan 7 false");
+    }
+
+    #[test]
+    fn loops_are_sorted_by_their_keys() {
+        let rows: &[&[&str]] = &[
+            &["item10", "b", "10"],
+            &["item2", "a", "9"],
+            &["Item3", "b", "x"],
+            &["item1", "a", "100"],
+        ];
+        let sorted = |clauses: &str| {
+            let template = format!(
+                "{{{{ OUTPUT out }}}}{{{{ LOOP(i) table as row {} SEPARATOR \",\" }}}}{{{{ i }}}}{{{{ row[0] }}}}{{{{ END }}}}",
+                clauses
+            );
+            render_with_table(&template, rows)
+        };
+        assert_eq!(sorted("SORT BY row[0]"), "0Item3,1item1,2item10,3item2");
+        assert_eq!(
+            sorted("SORT BY row[0] NATURAL"),
+            "0Item3,1item1,2item2,3item10"
+        );
+        assert_eq!(sorted("SORT BY row[2]"), "0item2,1item10,2item1,3Item3");
+        assert_eq!(
+            sorted("SORT BY row[2] LEXICAL"),
+            "0item10,1item1,2item2,3Item3"
+        );
+        assert_eq!(
+            sorted("SORT BY row[2] DESC NUMERIC"),
+            "0Item3,1item1,2item10,3item2"
+        );
+        assert_eq!(
+            sorted("SORT BY row[1], row[0] DESC"),
+            "0item2,1item1,2item10,3Item3"
+        );
+        assert_eq!(
+            sorted("SORT BY row[1] REVERSE"),
+            "0Item3,1item10,2item1,3item2"
+        );
+        assert_eq!(sorted("WHERE row[1] == \"a\" REVERSE"), "0item1,1item2");
+        assert_eq!(
+            sorted("SORT BY row"),
+            "Error at line number 1\nCannot sort by row, it is a collection"
+        );
+        assert_eq!(
+            sorted("SORT BY row[0] GROUP BY row[1] AS rows"),
+            "Error at line number 1\nGROUP is out of place, the clauses of a LOOP must be written in the order JOIN, WHERE, DISTINCT, GROUP BY, SORT BY, REVERSE, SEPARATOR, and each one only once (except for JOIN)"
+        );
+        let rows: &[&[&str]] = &[&["1a", "10", "9", "nan", "1.10", "1.1"]];
+        let template = "{{ OUTPUT out }}{{ LOOP table[0] as cell SORT BY cell SEPARATOR \";\" }}{{ cell }}{{ END }}";
+        assert_eq!(render_with_table(template, rows), "1.10;1.1;9;10;1a;nan");
+    }
+
+    #[test]
+    fn grouped_loops_go_through_each_key_once() {
+        let rows: &[&[&str]] = &[
+            &["key", "screen"],
+            &["title", "home"],
+            &["save", "settings"],
+            &["logout", "home"],
+        ];
+        let template = "{{ OUTPUT out }}{{ LOOP(i) table[1, ..] as row GROUP BY row[1] AS group SEPARATOR \" \" }}{{ i }}.{{ group_key }}:{{ LOOP group as item SEPARATOR \",\" }}{{ item[0] }}{{ END }}{{ END }}";
+        assert_eq!(
+            render_with_table(template, rows),
+            "0.home:title,logout 1.settings:save"
+        );
+        let template = "{{ OUTPUT out }}{{ LOOP table[1, ..] as row WHERE row[0] != \"title\" GROUP BY row[1] AS group SORT BY row[1] DESC }}{{ row[1] }}={{ group | length }} {{ END }}";
+        assert_eq!(render_with_table(template, rows), "settings=1 home=1 ");
+        let template = "{{ OUTPUT out }}{{ LOOP table[1, ..] as row GROUP BY row[1] AS (screen, items) }}{{ LOOP items as item GROUP BY item[0] == \"title\" AS titles }}{{ screen }}/{{ titles_key }}={{ titles | length }} {{ END }}{{ END }}";
+        assert_eq!(
+            render_with_table(template, rows),
+            "home/true=1 home/false=1 settings/false=1 "
+        );
+    }
+
+    #[test]
+    fn distinct_loops_skip_repeated_items() {
+        let rows: &[&[&str]] = &[
+            &["key", "platform"],
+            &["a", "ios"],
+            &["b", "android"],
+            &["c", "ios"],
+            &["d", "web"],
+            &["a", "ios"],
+        ];
+        let template = "{{ OUTPUT out }}{{ LOOP(i) table[1, ..] as row DISTINCT BY row[1] }}{{ i }}{{ row[1] }} {{ END }}";
+        assert_eq!(render_with_table(template, rows), "0ios 1android 2web ");
+        let template = "{{ OUTPUT out }}{{ LOOP table[2, ..] as row WHERE row[0] != \"d\" DISTINCT }}{{ row[0] }}{{ END }}";
+        assert_eq!(render_with_table(template, rows), "bca");
+        let rows: &[&[&str]] = &[&["ios", "web", "ios", "android", "web"]];
+        let template = "{{ OUTPUT out }}{{ LOOP(i, j) table[0][1, ..] as cell DISTINCT }}{{ i }}{{ j }}{{ cell }} {{ END }}";
+        assert_eq!(render_with_table(template, rows), "01web 12ios 23android ");
+        let template = "{{ OUTPUT out }}{{ SET platforms = table[0] | unique }}{{ LOOP platforms as cell }}{{ cell }} {{ END }}{{ platforms | length }}";
+        assert_eq!(render_with_table(template, rows), "ios web android 3");
+    }
+
+    #[test]
+    fn loops_go_through_columns_and_transposed_tables() {
+        let template = "{{ OUTPUT out }}{{ LOOP column(table, 1) as cell SEPARATOR \",\" }}{{ cell }}{{ END }}";
+        assert_eq!(render(template), "en,Title");
+        let template =
+            "{{ OUTPUT out }}{{ LOOP table | transpose as row }}{{ row[0] }}{{ row[1] }};{{ END }}";
+        assert_eq!(render(template), "keytitle;enTitle;ar;");
+        let template = "{{ OUTPUT out }}{{ LOOP 5 as i }}{{ i }}{{ END }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nAttempt to loop on a non-iterable"
+        );
+    }
+
+    #[test]
+    fn joins_and_lookups_match_rows_of_another_collection() {
+        let rows: &[&[&str]] = &[&["a", "b"], &["b", "c"], &["c", "x"]];
+        let template = "{{ OUTPUT out }}{{ LOOP(i) table as row JOIN table AS next ON next[0] == row[1] }}{{ i }}{{ row[0] }}{{ next[1] }} {{ END }}";
+        assert_eq!(render_with_table(template, rows), "0ac 1bx ");
+        let template = "{{ OUTPUT out }}{{ LOOP table as row JOIN table[1, ..] ON table[0] == row[1] WHERE table[1] != \"x\" }}{{ row[0] }}{{ table[1] }}{{ END }}";
+        assert_eq!(render_with_table(template, rows), "ac");
+        let template = "{{ OUTPUT out }}{{ SET found = lookup(table, 0, \"b\") }}{{ found[1] }} {{ table | lookup(1, \"x\") | length }} {{ IF lookup(table, 0, \"z\") is empty }}none{{ END }}";
+        assert_eq!(render_with_table(template, rows), "c 2 none");
+        let template = "{{ OUTPUT out }}{{ lookup() }}";
+        assert_eq!(
+            render_with_table(template, rows),
+            "Error at line number 1\nThe lookup function needs a value to work on, as in lookup(value)"
+        );
+    }
+
+    #[test]
+    fn ranges_loop_over_numbers() {
+        let template = "{{ OUTPUT out }}{{ LOOP 0..4 as i SEPARATOR \",\" }}{{ i * 2 }}{{ END }}";
+        assert_eq!(render(template), "0,2,4,6");
+        let template = "{{ OUTPUT out }}{{ LOOP(n) -1..length(table[0]) - 1 as i WHERE i != 0 REVERSE }}{{ n }}{{ i }} {{ END }}";
+        assert_eq!(render(template), "01 1-1 ");
+        let template = "{{ OUTPUT out }}{{ LOOP 3..1 as i }}{{ i }}{{ END }}{{ LOOP 1..3 as i }}{{ table[i][0] }}{{ END }}";
+        assert_eq!(render(template), "title");
+        let template = "{{ OUTPUT out }}{{ LOOP 0..table[1][1] as i }}{{ i }}{{ END }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 1\nA range can only be bounded by whole numbers, found table"
+        );
+        let template = "{{ OUTPUT out }}\n{{ LOOP 0..3000000000 as i }}{{ i }}{{ END }}";
+        assert_eq!(
+            render(template),
+            "Error at line number 2\nThe range 0..3000000000 has 3000000000 numbers, a range can have at most 1000000"
+        );
+    }
+}
@@ -1,25 +1,29 @@
 use crate::parser::scope::Scope;
 use crate::parser::var_type::Var;
 use crate::parser::var_type::VarType;
+use std::collections::HashMap;
+use std::collections::HashSet;
 
 pub struct LoopIterator<'a> {
-    _start: usize,
-    end: usize,
+    indexes: Vec<usize>,
     scope: &'a Scope<'a>,
     loop_index: usize,
-    collection_index: usize,
     collection: VarType,
     loop_variable_name: Option<String>,
     collection_variable_name: Option<String>,
     as_variable_name: Option<String>,
+    group_variable_names: Option<(String, String)>,
+    groups: Vec<(VarType, Vec<usize>)>,
+    joins: Vec<(String, HashMap<usize, VarType>)>,
 }
 
 impl<'a> LoopIterator<'a> {
+    /// Create a loop over the items of `collection` at the given indexes. The collection can
+    /// also be the first number of a range, where each index is how far a number is from it
     pub fn new(
         scope: &'a Scope,
         collection: VarType,
-        min: usize,
-        max: usize,
+        indexes: Vec<usize>,
         loop_variable_name: Option<String>,
         collection_variable_name: Option<String>,
         as_variable_name: Option<String>,
@@ -27,67 +31,220 @@ impl<'a> LoopIterator<'a> {
         LoopIterator {
             scope,
             collection,
-            _start: min,
-            end: max,
+            indexes,
             loop_variable_name,
             collection_variable_name,
             as_variable_name,
-            collection_index: min,
+            group_variable_names: None,
+            groups: vec![],
+            joins: vec![],
             loop_index: 0,
         }
     }
-}
 
-impl<'a> Iterator for LoopIterator<'a> {
-    type Item = Scope<'a>;
+    /// Call `f` with the scope of every item that the loop goes through, in order
+    pub fn map_scopes<T, E>(
+        &self,
+        mut f: impl FnMut(&mut Scope<'a>) -> Result<T, E>,
+    ) -> Result<Vec<T>, E> {
+        let mut results = vec![];
+        for position in 0..self.indexes.len() {
+            results.push(f(&mut self.scope_at(position))?);
+        }
+        Ok(results)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.collection_index < self.end {
-            let mut scope = Scope::with_parent(self.scope);
+    /// Only go through the items at the given positions of the loop, in the given order
+    pub fn select(&mut self, positions: Vec<usize>) {
+        if !self.groups.is_empty() {
+            self.groups = positions
+                .iter()
+                .map(|&position| self.groups[position].clone())
+                .collect();
+        }
+        self.indexes = positions
+            .into_iter()
+            .map(|position| self.indexes[position])
+            .collect();
+    }
+
+    /// Bind the row of another collection that matches each item to `variable_name`, where
+    /// `matches` has the match of every position of the loop. Items without a match are skipped
+    pub fn join(&mut self, variable_name: String, matches: Vec<Option<VarType>>) {
+        let mut rows = HashMap::new();
+        let mut positions = vec![];
+        for (position, row) in matches.into_iter().enumerate() {
+            if let Some(row) = row {
+                rows.insert(self.indexes[position], row);
+                positions.push(position);
+            }
+        }
+        self.select(positions);
+        self.joins.push((variable_name, rows));
+    }
+
+    /// Skip the items that are the same as an item before them
+    pub fn remove_repeated_items(&mut self) {
+        let mut seen = HashSet::new();
+        let collection = &self.collection;
+        self.indexes.retain(|&index| match collection {
+            VarType::Table(var) => seen.insert(var.data[index].clone()),
+            VarType::Row(var) => seen.insert(vec![var.data[index].clone()]),
+            VarType::Number(_) => true,
+            _ => panic!("Attempt to loop on a non-iterable"),
+        });
+    }
+
+    /// Go through each group once instead of each item, where a group is a key along with
+    /// the positions of its items in the loop. The key and the items of a group are bound to
+    /// `key_variable_name` and `group_variable_name`, and the first item is used as the
+    /// current item
+    pub fn group(
+        &mut self,
+        key_variable_name: String,
+        group_variable_name: String,
+        groups: Vec<(VarType, Vec<usize>)>,
+    ) {
+        self.groups = groups
+            .into_iter()
+            .map(|(key, positions)| {
+                let indexes = positions
+                    .into_iter()
+                    .map(|position| self.indexes[position])
+                    .collect();
+                (key, indexes)
+            })
+            .collect();
+        self.indexes = self.groups.iter().map(|(_, indexes)| indexes[0]).collect();
+        self.group_variable_names = Some((key_variable_name, group_variable_name));
+    }
+
+    /// Go through the items of the loop in the opposite order
+    pub fn reverse(&mut self) {
+        self.indexes.reverse();
+        self.groups.reverse();
+    }
+
+    /// Create the scope for the item at the given position of the loop
+    fn scope_at(&self, position: usize) -> Scope<'a> {
+        let collection_index = self.indexes[position];
+        let length = self.indexes.len();
+        let mut scope = Scope::with_parent(self.scope);
+
+        // The details of the loop are read as `loop.first` and so on, which cannot clash with
+        // the names of variables since `loop` is a keyword
+        scope.insert(
+            String::from("loop.index"),
+            VarType::Number(Var::new(position as i64)),
+        );
+        scope.insert(
+            String::from("loop.collection_index"),
+            VarType::Number(Var::new(collection_index as i64)),
+        );
+        scope.insert(
+            String::from("loop.first"),
+            VarType::Bool(Var::new(position == 0)),
+        );
+        scope.insert(
+            String::from("loop.last"),
+            VarType::Bool(Var::new(position + 1 == length)),
+        );
+        scope.insert(
+            String::from("loop.length"),
+            VarType::Number(Var::new(length as i64)),
+        );
+        scope.insert(
+            String::from("loop.remaining"),
+            VarType::Number(Var::new((length - position - 1) as i64)),
+        );
 
+        if let Some(variable) = &self.loop_variable_name {
+            scope.insert(variable.clone(), VarType::Number(Var::new(position as i64)));
+        }
+
+        if let Some(variable) = &self.collection_variable_name {
             scope.insert(
-                String::from("loop_index"),
-                VarType::Number(Var::new(self.loop_index)),
+                variable.clone(),
+                VarType::Number(Var::new(collection_index as i64)),
             );
+        }
+
+        for (variable, rows) in &self.joins {
+            scope.insert(variable.clone(), rows[&collection_index].clone());
+        }
+
+        if let Some((key_variable, variable)) = &self.group_variable_names {
+            let (key, indexes) = &self.groups[position];
+            scope.insert(key_variable.clone(), key.clone());
+            scope.insert(variable.clone(), self.group_at(indexes));
+        }
+
+        if let Some(variable) = &self.as_variable_name {
             scope.insert(
-                String::from("collection_index"),
-                VarType::Number(Var::new(self.collection_index)),
+                variable.clone(),
+                item_at(&self.collection, collection_index),
             );
+        }
+        scope
+    }
 
-            if let Some(variable) = &self.loop_variable_name {
-                scope.insert(variable.clone(), VarType::Number(Var::new(self.loop_index)));
+    /// Take the items of the collection at the given indexes
+    fn group_at(&self, indexes: &[usize]) -> VarType {
+        match &self.collection {
+            VarType::Table(var) => VarType::Table(Var::with_headers(
+                indexes
+                    .iter()
+                    .map(|&index| var.data[index].clone())
+                    .collect(),
+                var.headers.clone(),
+            )),
+            VarType::Row(var) => {
+                let headers = var.headers.as_ref().map(|headers| {
+                    indexes
+                        .iter()
+                        .map(|&index| headers.get(index).cloned().unwrap_or_default())
+                        .collect()
+                });
+                VarType::Row(Var::with_headers(
+                    indexes
+                        .iter()
+                        .map(|&index| var.data[index].clone())
+                        .collect(),
+                    headers,
+                ))
             }
+            VarType::Number(var) => VarType::Row(Var::new(
+                indexes
+                    .iter()
+                    .map(|&index| (var.data + index as i64).to_string())
+                    .collect(),
+            )),
+            _ => panic!("Attempt to loop on a non-iterable"),
+        }
+    }
+}
 
-            if let Some(variable) = &self.collection_variable_name {
-                scope.insert(
-                    variable.clone(),
-                    VarType::Number(Var::new(self.collection_index)),
-                );
-            }
+/// Take the item of a collection at the given index, where the items of a table are its rows,
+/// the items of a row are its cells and the items of a range are its numbers
+pub fn item_at(collection: &VarType, index: usize) -> VarType {
+    match collection {
+        VarType::Table(var) => VarType::Row(Var::with_headers(
+            var.data[index].clone(),
+            var.headers.clone(),
+        )),
+        VarType::Row(var) => VarType::Value(Var::new(var.data[index].clone())),
+        VarType::Number(var) => VarType::Number(Var::new(var.data + index as i64)),
+        _ => panic!("Attempt to loop on a non-iterable"),
+    }
+}
 
-            match &self.collection {
-                VarType::Table(var) => {
-                    if let Some(variable) = &self.as_variable_name {
-                        scope.insert(
-                            variable.clone(),
-                            VarType::Row(Var::new(var.data[self.collection_index].clone())),
-                        );
-                    }
-                }
-                VarType::Row(var) => {
-                    if let Some(variable) = &self.as_variable_name {
-                        scope.insert(
-                            variable.clone(),
-                            VarType::Value(Var::new(var.data[self.collection_index].clone())),
-                        );
-                    }
-                }
-                _ => panic!("Attempt to loop on a non-iterable"),
-            };
+impl<'a> Iterator for LoopIterator<'a> {
+    type Item = Scope<'a>;
 
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.loop_index < self.indexes.len() {
+            let scope = self.scope_at(self.loop_index);
             self.loop_index += 1;
-            self.collection_index += 1;
-
             Some(scope)
         } else {
             None
@@ -1,4 +1,5 @@
 pub mod interpreter;
+pub mod filters;
 pub mod loop_iterator;
 pub mod importer;
 pub mod interpreter_result;
\ No newline at end of file
@@ -20,3 +20,10 @@ pub fn generate_with_scope(scope: &mut Scope) {
     let path = env::current_dir().expect("Could not read current directory");
     FileWalker::walk_directory_with_scope(&path, scope);
 }
+
+/// Walk through the current directory and interpret all hamster_wheel files
+/// Uses the given `Scope` as the base, and turns on the given modes in all files, as if they started with `{{ MODE name }}`
+pub fn generate_with_modes(scope: &mut Scope, modes: &[String]) {
+    let path = env::current_dir().expect("Could not read current directory");
+    FileWalker::walk_directory_with_modes(&path, scope, modes);
+}
@@ -10,6 +10,15 @@ fn main() {
         .version("0.2.1")
         .author("OsamaElHariri <yamsandbread@gmail.com>")
         .about("Looping code generator that allows for generating code over a collection")
+        .arg(
+            Arg::with_name("mode")
+                .long("mode")
+                .help("Turn on a mode in every file, as if it started with {{ MODE name }}")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&["clamp", "strict"]),
+        )
         .subcommand(
             SubCommand::with_name("file")
             .help("Import a collection from a file")
@@ -49,6 +58,10 @@ fn main() {
         let mut importer = Importer::new(path);
         importer.update_with_file(&mut base_scope, String::from(name), String::from(file_path));
     }
-    hamster_wheel::generate_with_scope(&mut base_scope);
+    let modes: Vec<String> = match matches.values_of("mode") {
+        Some(modes) => modes.map(String::from).collect(),
+        None => vec![],
+    };
+    hamster_wheel::generate_with_modes(&mut base_scope, &modes);
     println!("Hamster Wheel Done!");
 }
@@ -4,6 +4,10 @@ use crate::tokenizer::tokenizer::Tokenizer;
 use crate::tree_nodes::tree_nodes::*;
 use std::fmt;
 
+/// `GROUP` is only a keyword right before `BY` in a loop, so that it can still be
+/// used to name the group, as in `GROUP BY row[0] AS group`
+const GROUP_KEYWORD: &str = "group";
+
 pub struct Parser<'a> {
     pub text: &'a str,
     pub lexer: Tokenizer<'a>,
@@ -15,7 +19,7 @@ impl<'a> Parser<'a> {
     pub fn new(text: &'a str) -> Parser {
         Parser {
             text,
-            lexer: Tokenizer::new(text),
+            lexer: Tokenizer::from_template(text),
             current_line: 0,
         }
     }
@@ -25,16 +29,122 @@ impl<'a> Parser<'a> {
         self.start()
     }
 
+    /// Parse this `Parser`'s source text as a partial, which is a file that is included
+    /// in other files and does not specify an output file of its own
+    pub fn parse_partial(&mut self) -> Result<Expr, ParseError> {
+        self.top_level_block()
+    }
+
+    /// Check if this `Parser`'s source text starts by specifying an output file,
+    /// as opposed to being a partial
+    pub fn declares_output(&mut self) -> bool {
+        self.skip_leading_comments();
+        self.lexer.reset_peek();
+        self.lexer.info().token == Token::LeftMustache && self.lexer.peek().token == Token::Output
+    }
+
+    /// Find the paths of the files that this `Parser`'s source text includes or extends
+    pub fn referenced_files(&mut self) -> Vec<String> {
+        let mut paths = vec![];
+        while self.lexer.info().token != Token::EOF {
+            let is_mustache = self.lexer.info().token == Token::LeftMustache;
+            self.lexer.advance();
+            if !is_mustache {
+                continue;
+            }
+            self.lexer.reset_peek();
+            let token = self.lexer.info().token.clone();
+            let is_statement = starts_statement(&token, &self.lexer.peek().token);
+            if let (Token::Include, true) | (Token::Extends, true) = (token, is_statement) {
+                self.lexer.advance();
+                if let Ok(path) = self.file_path("The file to reference must be specified") {
+                    paths.push(path.slice);
+                }
+            }
+        }
+        paths
+    }
+
     fn start(&mut self) -> Result<Expr, ParseError> {
+        self.skip_leading_comments();
+        let output = self.output()?;
+        self.lexer.reset_peek();
+        // Whitespace between the output and the escaper is part of the header
+        let is_whitespace_next =
+            self.lexer.info().token == Token::Text && self.lexer.info().slice.trim().is_empty();
+        let mut is_escape_next = self.lexer.info().token == Token::LeftMustache;
+        if is_whitespace_next {
+            is_escape_next = self.lexer.peek().token == Token::LeftMustache;
+        }
+        let is_escape_next = is_escape_next && self.lexer.peek().token == Token::Escape;
+        let escape = if is_escape_next {
+            if self.lexer.info().token == Token::Text {
+                self.consume(Token::Text)?;
+            }
+            Some(self.escape()?)
+        } else {
+            None
+        };
+
         Ok(Expr::Start(Box::new(StartExpr {
-            output: self.output()?,
-            expr: self.block()?,
+            output,
+            escape,
+            expr: self.top_level_block()?,
         })))
     }
 
+    /// Parse the block that makes up the rest of the file. Unlike a nested block, it can only
+    /// be ended by the end of the file, so an `ELSE` or `END` that closes nothing is an error
+    fn top_level_block(&mut self) -> Result<Expr, ParseError> {
+        let block = self.block()?;
+        if self.lexer.info().token == Token::EOF {
+            return Ok(block);
+        }
+        self.lexer.reset_peek();
+        let keyword = self.lexer.peek().slice.to_uppercase();
+        let start_index = self.lexer.info().start;
+        Err(ParseError {
+            msg: format!(
+                "Unexpected {}, there is no LOOP, IF or other block for it to close",
+                keyword
+            ),
+            line_number: self.get_line_count_at_index(start_index),
+        })
+    }
+
+    /// Comments can describe the file before its output is declared
+    fn skip_leading_comments(&mut self) {
+        while self.lexer.info().token == Token::Comment
+            || (self.lexer.info().token == Token::Text && self.lexer.info().slice.trim().is_empty())
+        {
+            self.lexer.advance();
+        }
+    }
+
+    fn escape(&mut self) -> Result<EscapeExpr, ParseError> {
+        Ok(EscapeExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            escape: self.consume(Token::Escape)?,
+            escaper: self.consume(Token::Variable)?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })
+    }
+
     fn output(&mut self) -> Result<OutputExpr, ParseError> {
         let left_mustache = self.consume(Token::LeftMustache)?;
         let output = self.consume(Token::Output)?;
+        let file_path =
+            self.file_path("An output file must be specified at the top of the file")?;
+        Ok(OutputExpr {
+            left_mustache,
+            output,
+            file_path,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })
+    }
+
+    /// Read everything up to the end of the mustache as a single path
+    fn file_path(&mut self, missing_msg: &str) -> Result<InfoToken, ParseError> {
         let mut vars = vec![];
         while self.lexer.info().token != Token::EOF
             && self.lexer.info().token != Token::RightMustache
@@ -45,78 +155,265 @@ impl<'a> Parser<'a> {
         }
         if vars.len() == 0 {
             return Err(ParseError {
-                msg: String::from("An output file must be specified at the top of the file"),
+                msg: String::from(missing_msg),
                 line_number: self.current_line,
             });
         }
         let value_text = self.text[vars[0].start..vars[vars.len() - 1].end].to_string();
-        let value = InfoToken {
+        Ok(InfoToken {
             token: Token::Variable,
             slice: value_text,
             start: vars[0].start,
             end: vars.last().expect("non-empty").end,
-        };
-        Ok(OutputExpr {
-            left_mustache,
-            output,
-            file_path: value,
-            right_mustache: self.consume(Token::RightMustache)?,
         })
     }
 
+    fn include(&mut self) -> Result<Expr, ParseError> {
+        Ok(Expr::Include(Box::new(IncludeExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            include: self.consume(Token::Include)?,
+            file_path: self.file_path("The file to include must be specified")?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })))
+    }
+
     fn block(&mut self) -> Result<Expr, ParseError> {
         let mut blocks: Vec<Expr> = vec![];
         let mut imports = vec![];
         while self.lexer.info().token != Token::EOF {
-            if self.lexer.info().token == Token::LeftMustache {
-                let next_info = self.lexer.peek();
-                match next_info.token {
-                    Token::Loop => blocks.push(self.r#loop()?),
-                    Token::Import => imports.push(self.import_stmt()?),
-                    Token::End => break,
-                    _ => blocks.push(self.mustache_accessor()?),
-                };
-            } else {
-                blocks.push(self.anything());
+            match self.lexer.info().token {
+                Token::LeftMustache => {
+                    self.lexer.reset_peek();
+                    let keyword = self.lexer.peek().token.clone();
+                    let next = self.lexer.peek().token.clone();
+                    // A keyword that starts a tag is read as a name when it is used like one,
+                    // as in `{{ set }}` or `{{ block | upper }}`. `{{ RAW }}` always starts
+                    // a raw block, since the `Tokenizer` reads the text after it as it is
+                    let is_statement = starts_statement(&keyword, &next);
+                    match keyword {
+                        Token::End | Token::Else => break,
+                        Token::Import => imports.push(self.import_stmt()?),
+                        Token::Delimiters => self.delimiters()?,
+                        Token::Raw if next == Token::RightMustache => blocks.push(self.raw()?),
+                        _ if !is_statement => blocks.push(self.mustache_accessor()?),
+                        Token::Loop => blocks.push(self.r#loop()?),
+                        Token::If => blocks.push(self.r#if()?),
+                        Token::Include => blocks.push(self.include()?),
+                        Token::Define => blocks.push(self.define()?),
+                        Token::Call => blocks.push(self.call()?),
+                        Token::Extends => blocks.push(self.extends()?),
+                        Token::Block => blocks.push(self.named_block()?),
+                        Token::Set => blocks.push(self.set()?),
+                        Token::Mode => blocks.push(self.mode()?),
+                        _ => blocks.push(self.mustache_accessor()?),
+                    };
+                }
+                Token::Comment | Token::Error => self.comment()?,
+                _ => blocks.push(self.anything()?),
             };
         }
 
         Ok(Expr::Block(Box::new(BlockExpr { imports, blocks })))
     }
 
-    fn anything(&mut self) -> Expr {
-        let mut tokens: Vec<InfoToken> = vec![];
-        while self.lexer.info().token != Token::EOF
-            && self.lexer.info().token != Token::LeftMustache
-        {
-            let info = self.lexer.info();
-            let mut start = info.start - 1;
-            while start > 0 && self.text.chars().nth(start).expect("").is_whitespace() {
-                start -= 1
-            }
+    fn set(&mut self) -> Result<Expr, ParseError> {
+        Ok(Expr::Set(Box::new(SetExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            set: self.consume(Token::Set)?,
+            name: self.name()?,
+            assign: self.consume(Token::Assign)?,
+            value: self.condition()?,
+            filters: self.filters()?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })))
+    }
 
-            let mut end = info.end;
-            while end < self.text.len() && self.text.chars().nth(end).expect("").is_whitespace() {
-                end += 1;
+    fn mode(&mut self) -> Result<Expr, ParseError> {
+        Ok(Expr::Mode(Box::new(ModeExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            mode: self.consume(Token::Mode)?,
+            name: self.consume(Token::Variable)?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })))
+    }
+
+    fn extends(&mut self) -> Result<Expr, ParseError> {
+        Ok(Expr::Extends(Box::new(ExtendsExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            extends: self.consume(Token::Extends)?,
+            file_path: self.file_path("The file to extend must be specified")?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })))
+    }
+
+    fn named_block(&mut self) -> Result<Expr, ParseError> {
+        Ok(Expr::NamedBlock(Box::new(NamedBlockExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            named_block: self.consume(Token::Block)?,
+            name: self.name()?,
+            right_mustache: self.consume(Token::RightMustache)?,
+            block: Box::new(self.block()?),
+            block_end: self.loop_end()?,
+        })))
+    }
+
+    fn define(&mut self) -> Result<Expr, ParseError> {
+        let left_mustache = self.consume(Token::LeftMustache)?;
+        let define = self.consume(Token::Define)?;
+        let name = self.name()?;
+        let left_paren = self.consume(Token::LeftParentheses)?;
+        let mut parameters = vec![];
+        if self.lexer.info().token != Token::RightParentheses {
+            parameters.push(self.name()?);
+            while self.lexer.info().token == Token::Comma {
+                self.consume(Token::Comma)?;
+                parameters.push(self.name()?);
             }
-            tokens.push(InfoToken {
-                token: info.token.clone(),
-                slice: info.slice.clone(),
-                start: start + 1,
-                end: end,
+        }
+
+        Ok(Expr::Define(Box::new(DefineExpr {
+            left_mustache,
+            define,
+            name,
+            left_paren,
+            parameters,
+            right_paren: self.consume(Token::RightParentheses)?,
+            right_mustache: self.consume(Token::RightMustache)?,
+            block: Box::new(self.block()?),
+            define_end: self.loop_end()?,
+        })))
+    }
+
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        Ok(Expr::Call(Box::new(CallExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            call: self.consume(Token::Call)?,
+            name: self.name()?,
+            arguments: self.arguments()?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })))
+    }
+
+    fn raw(&mut self) -> Result<Expr, ParseError> {
+        let left_mustache = self.consume(Token::LeftMustache)?;
+        let raw = self.consume(Token::Raw)?;
+        let right_mustache = self.consume(Token::RightMustache)?;
+        let text = match self.lexer.info().token {
+            Token::Text => Some(self.consume(Token::Text)?),
+            _ => None,
+        };
+
+        Ok(Expr::Raw(Box::new(RawExpr {
+            left_mustache,
+            raw,
+            right_mustache,
+            text,
+            raw_end: self.raw_end()?,
+        })))
+    }
+
+    fn raw_end(&mut self) -> Result<EndExpr, ParseError> {
+        Ok(EndExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            end: self.consume(Token::EndRaw)?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })
+    }
+
+    /// Skip over a change of delimiters, which the `Tokenizer` applies by itself
+    fn delimiters(&mut self) -> Result<(), ParseError> {
+        self.consume(Token::LeftMustache)?;
+        self.consume(Token::Delimiters)?;
+        let mut delimiters = vec![];
+        while self.lexer.info().token == Token::Text {
+            delimiters.push(self.consume(Token::Text)?);
+        }
+        if delimiters.len() != 2 {
+            return Err(ParseError {
+                msg: String::from(
+                    "Delimiters must be in the form of: \"DELIMITERS left right\", for example \"DELIMITERS <% %>\"",
+                ),
+                line_number: self.current_line,
             });
-            self.lexer.advance();
         }
+        self.consume(Token::RightMustache)?;
+        Ok(())
+    }
 
-        Expr::Anything(Box::new(AnythingExpr { tokens }))
+    /// Skip over a comment, which produces no output
+    fn comment(&mut self) -> Result<(), ParseError> {
+        if self.lexer.info().token == Token::Error {
+            let start = self.lexer.info().start;
+            self.current_line = self.get_line_count_at_index(start);
+            return Err(ParseError {
+                msg: format!(
+                    "The comment opened with {} is never closed",
+                    self.lexer.info().slice
+                ),
+                line_number: self.current_line,
+            });
+        }
+        self.consume(Token::Comment)?;
+        Ok(())
+    }
+
+    fn anything(&mut self) -> Result<Expr, ParseError> {
+        let mut tokens: Vec<InfoToken> = vec![self.consume(Token::Text)?];
+        while self.lexer.info().token == Token::Text {
+            tokens.push(self.consume(Token::Text)?);
+        }
+
+        Ok(Expr::Anything(Box::new(AnythingExpr { tokens })))
     }
 
     fn mustache_accessor(&mut self) -> Result<Expr, ParseError> {
-        Ok(Expr::MustacheAccessor(MustacheAccessorExpr {
+        Ok(Expr::MustacheAccessor(Box::new(MustacheAccessorExpr {
             left_mustache: self.consume(Token::LeftMustache)?,
-            accessor: self.accessor()?,
+            value: self.condition()?,
+            filters: self.filters()?,
             right_mustache: self.consume(Token::RightMustache)?,
-        }))
+        })))
+    }
+
+    fn filters(&mut self) -> Result<Vec<FilterExpr>, ParseError> {
+        let mut filters = vec![];
+        while self.lexer.info().token == Token::Pipe {
+            filters.push(self.filter()?);
+        }
+        Ok(filters)
+    }
+
+    fn filter(&mut self) -> Result<FilterExpr, ParseError> {
+        let pipe = self.consume(Token::Pipe)?;
+        let name = self.identifier()?;
+        let arguments = match self.lexer.info().token {
+            Token::LeftParentheses => Some(self.arguments()?),
+            _ => None,
+        };
+
+        Ok(FilterExpr {
+            pipe,
+            name,
+            arguments,
+        })
+    }
+
+    fn arguments(&mut self) -> Result<ArgumentsExpr, ParseError> {
+        let left_paren = self.consume(Token::LeftParentheses)?;
+        let mut arguments = vec![];
+        if self.lexer.info().token != Token::RightParentheses {
+            arguments.push(self.condition()?);
+            while self.lexer.info().token == Token::Comma {
+                self.consume(Token::Comma)?;
+                arguments.push(self.condition()?);
+            }
+        }
+
+        Ok(ArgumentsExpr {
+            left_paren,
+            arguments,
+            right_paren: self.consume(Token::RightParentheses)?,
+        })
     }
 
     fn r#loop(&mut self) -> Result<Expr, ParseError> {
@@ -127,13 +424,228 @@ impl<'a> Parser<'a> {
         })))
     }
 
+    fn r#if(&mut self) -> Result<Expr, ParseError> {
+        let if_start = self.if_start()?;
+        let block = Box::new(self.block()?);
+        let mut else_ifs = vec![];
+        let mut r#else = None;
+        loop {
+            self.lexer.reset_peek();
+            if self.lexer.info().token != Token::LeftMustache
+                || self.lexer.peek().token != Token::Else
+            {
+                break;
+            }
+            if self.lexer.peek().token == Token::If {
+                else_ifs.push(self.else_if()?);
+            } else {
+                r#else = Some(self.r#else()?);
+                break;
+            }
+        }
+
+        Ok(Expr::If(Box::new(IfExpr {
+            if_start,
+            block,
+            else_ifs,
+            r#else,
+            if_end: self.loop_end()?,
+        })))
+    }
+
+    fn if_start(&mut self) -> Result<IfStartExpr, ParseError> {
+        Ok(IfStartExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            r#if: self.consume(Token::If)?,
+            condition: self.condition()?,
+            right_mustache: self.consume(Token::RightMustache)?,
+        })
+    }
+
+    fn else_if(&mut self) -> Result<ElseIfExpr, ParseError> {
+        Ok(ElseIfExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            r#else: self.consume(Token::Else)?,
+            r#if: self.consume(Token::If)?,
+            condition: self.condition()?,
+            right_mustache: self.consume(Token::RightMustache)?,
+            block: Box::new(self.block()?),
+        })
+    }
+
+    fn r#else(&mut self) -> Result<ElseExpr, ParseError> {
+        Ok(ElseExpr {
+            left_mustache: self.consume(Token::LeftMustache)?,
+            r#else: self.consume(Token::Else)?,
+            right_mustache: self.consume(Token::RightMustache)?,
+            block: Box::new(self.block()?),
+        })
+    }
+
+    fn condition(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.and_condition()?;
+        while self.lexer.info().token == Token::Or {
+            let operator = self.consume(Token::Or)?;
+            left = Expr::Binary(Box::new(BinaryExpr {
+                left,
+                operator,
+                right: self.and_condition()?,
+            }));
+        }
+        Ok(left)
+    }
+
+    fn and_condition(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.not_condition()?;
+        while self.lexer.info().token == Token::And {
+            let operator = self.consume(Token::And)?;
+            left = Expr::Binary(Box::new(BinaryExpr {
+                left,
+                operator,
+                right: self.not_condition()?,
+            }));
+        }
+        Ok(left)
+    }
+
+    fn not_condition(&mut self) -> Result<Expr, ParseError> {
+        self.lexer.reset_peek();
+        if self.lexer.info().token == Token::Not && !follows_operand(&self.lexer.peek().token) {
+            Ok(Expr::Unary(Box::new(UnaryExpr {
+                operator: self.consume(Token::Not)?,
+                operand: self.not_condition()?,
+            })))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let left = self.is_empty()?;
+        match self.lexer.info().token {
+            Token::Equal
+            | Token::NotEqual
+            | Token::LessThan
+            | Token::LessThanOrEqual
+            | Token::GreaterThan
+            | Token::GreaterThanOrEqual => {
+                let token = self.lexer.info().token.clone();
+                Ok(Expr::Binary(Box::new(BinaryExpr {
+                    left,
+                    operator: self.consume(token)?,
+                    right: self.is_empty()?,
+                })))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn is_empty(&mut self) -> Result<Expr, ParseError> {
+        let operand = self.arithmetic()?;
+        if self.lexer.info().token != Token::Is {
+            return Ok(operand);
+        }
+        let is = self.consume(Token::Is)?;
+        let not = match self.lexer.info().token {
+            Token::Not => Some(self.consume(Token::Not)?),
+            _ => None,
+        };
+
+        Ok(Expr::IsEmpty(Box::new(IsEmptyExpr {
+            operand,
+            is,
+            not,
+            empty: self.consume(Token::Empty)?,
+        })))
+    }
+
+    fn arithmetic(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.term()?;
+        while self.lexer.info().token == Token::Plus || self.lexer.info().token == Token::Minus {
+            let token = self.lexer.info().token.clone();
+            left = Expr::Binary(Box::new(BinaryExpr {
+                left,
+                operator: self.consume(token)?,
+                right: self.term()?,
+            }));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.negation()?;
+        while let Token::Star | Token::Slash | Token::Percent = self.lexer.info().token {
+            let token = self.lexer.info().token.clone();
+            left = Expr::Binary(Box::new(BinaryExpr {
+                left,
+                operator: self.consume(token)?,
+                right: self.negation()?,
+            }));
+        }
+        Ok(left)
+    }
+
+    fn negation(&mut self) -> Result<Expr, ParseError> {
+        if self.lexer.info().token == Token::Minus {
+            Ok(Expr::Unary(Box::new(UnaryExpr {
+                operator: self.consume(Token::Minus)?,
+                operand: self.negation()?,
+            })))
+        } else {
+            self.operand()
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr, ParseError> {
+        match self.lexer.info().token {
+            Token::LeftParentheses => {
+                self.consume(Token::LeftParentheses)?;
+                let condition = self.condition()?;
+                self.consume(Token::RightParentheses)?;
+                Ok(condition)
+            }
+            Token::Number => Ok(Expr::Literal(LiteralExpr {
+                token: self.consume(Token::Number)?,
+            })),
+            Token::StringLiteral => Ok(Expr::Literal(LiteralExpr {
+                token: self.consume(Token::StringLiteral)?,
+            })),
+            _ => {
+                let array_accessor = self.array_accessor()?;
+                // A name followed by parentheses calls the filter of that name
+                let is_name =
+                    array_accessor.indexes.is_empty() && array_accessor.array_slice.is_none();
+                if is_name && self.lexer.info().token == Token::LeftParentheses {
+                    return Ok(Expr::Function(Box::new(FunctionExpr {
+                        name: array_accessor.variable,
+                        arguments: self.arguments()?,
+                    })));
+                }
+                if array_accessor.array_slice.is_some() {
+                    return Ok(Expr::Slice(Box::new(array_accessor)));
+                }
+                Ok(Expr::Accessor(AccessorExpr {
+                    variable: array_accessor.variable,
+                    indexes: array_accessor.indexes,
+                }))
+            }
+        }
+    }
+
     fn import_stmt(&mut self) -> Result<ImportExpr, ParseError> {
         let left_mustache = self.consume(Token::LeftMustache)?;
         let config = self.consume(Token::Import)?;
         let right_mustache = self.consume(Token::RightMustache)?;
         let mut configs = vec![];
-        while self.lexer.info().token != Token::LeftMustache {
-            configs.push(self.import_option()?);
+        loop {
+            match self.lexer.info().token {
+                Token::Text => {
+                    let text = self.consume(Token::Text)?;
+                    configs.append(&mut self.import_options(text)?);
+                }
+                Token::Comment | Token::Error => self.comment()?,
+                _ => break,
+            };
         }
 
         Ok(ImportExpr {
@@ -145,39 +657,71 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn import_option(&mut self) -> Result<ImportConfigOptionExpr, ParseError> {
-        let variable = self.consume(Token::Variable)?;
-        let colon = self.consume(Token::Colon)?;
-        let mut vars = vec![];
-        while self.lexer.info().token != Token::EOF
-            && self.lexer.info().token != Token::SemiColon
-            && self.lexer.info().token != Token::LeftMustache
-        {
-            let token = self.lexer.info().token.clone();
-            vars.push(self.consume(token)?);
+    /// Read the options written as text inside an import, each in the form of "name: value;"
+    fn import_options(
+        &mut self,
+        text: InfoToken,
+    ) -> Result<Vec<ImportConfigOptionExpr>, ParseError> {
+        let mut configs = vec![];
+        let mut start = text.start;
+        let mut rest = text.slice.as_str();
+        while let Some(semicolon_index) = rest.find(';') {
+            configs.push(self.import_option(start, &rest[..semicolon_index])?);
+            start += semicolon_index + 1;
+            rest = &rest[semicolon_index + 1..];
         }
-        if vars.len() == 0 {
+        if !rest.trim().is_empty() {
+            let option_start = start + rest.len() - rest.trim_start().len();
+            self.current_line = self.get_line_count_at_index(option_start);
             return Err(ParseError {
-                msg: String::from("Configs must be in the form of: \"name: value;\""),
+                msg: format!(
+                    "Expected SemiColon, found end of import.\nCurrent slice reads {}.",
+                    rest.trim()
+                ),
                 line_number: self.current_line,
             });
         }
-        let value_text = self.text[vars[0].start..vars[vars.len() - 1].end].to_string();
-        let value = InfoToken {
-            token: Token::Variable,
-            slice: value_text,
-            start: vars[0].start,
-            end: vars.last().expect("non-empty").end,
+        Ok(configs)
+    }
+
+    fn import_option(
+        &mut self,
+        start: usize,
+        option: &str,
+    ) -> Result<ImportConfigOptionExpr, ParseError> {
+        let colon_index = option.find(':');
+        let option_start = start + option.len() - option.trim_start().len();
+        self.current_line = self.get_line_count_at_index(option_start);
+        let (name, value) = match colon_index {
+            Some(index) => (&option[..index], &option[index + 1..]),
+            None => ("", ""),
         };
+        if name.trim().is_empty() || value.trim().is_empty() {
+            return Err(ParseError {
+                msg: String::from("Configs must be in the form of: \"name: value;\""),
+                line_number: self.current_line,
+            });
+        }
+        let colon_start = start + name.len();
 
         Ok(ImportConfigOptionExpr {
-            variable,
-            colon,
-            value: value,
-            semicolon: self.consume(Token::SemiColon)?,
+            variable: self.trimmed_token(Token::Variable, start, name),
+            colon: self.trimmed_token(Token::Colon, colon_start, ":"),
+            value: self.trimmed_token(Token::Variable, colon_start + 1, value),
+            semicolon: self.trimmed_token(Token::SemiColon, start + option.len(), ";"),
         })
     }
 
+    fn trimmed_token(&self, token: Token, start: usize, slice: &str) -> InfoToken {
+        let start = start + slice.len() - slice.trim_start().len();
+        InfoToken {
+            token,
+            slice: slice.trim().to_string(),
+            start,
+            end: start + slice.trim().len(),
+        }
+    }
+
     fn loop_start(&mut self) -> Result<LoopStartExpr, ParseError> {
         let left_mustache = self.consume(Token::LeftMustache)?;
         let r#loop = self.consume(Token::Loop)?;
@@ -185,24 +729,232 @@ impl<'a> Parser<'a> {
         if self.lexer.info().token == Token::LeftParentheses {
             loop_variable = Some(self.loop_variable()?);
         };
-        let array_accessor = self.array_accessor()?;
+        let source = self.loop_source()?;
         let mut as_variable: Option<AsVariableExpr> = None;
         if self.lexer.info().token == Token::As {
             as_variable = Some(self.as_variable()?);
         };
+        let mut joins = vec![];
+        while self.lexer.info().token == Token::Join {
+            joins.push(self.join()?);
+        }
+        let mut where_clause: Option<WhereExpr> = None;
+        if self.lexer.info().token == Token::Where {
+            where_clause = Some(WhereExpr {
+                r#where: self.consume(Token::Where)?,
+                condition: self.condition()?,
+            });
+        };
+        let mut distinct: Option<DistinctExpr> = None;
+        if self.lexer.info().token == Token::Distinct {
+            distinct = Some(self.distinct()?);
+        };
+        let mut group: Option<GroupExpr> = None;
+        let info = self.lexer.info();
+        if info.token == Token::Variable && info.slice.eq_ignore_ascii_case(GROUP_KEYWORD) {
+            group = Some(self.group()?);
+        };
+        let mut sort: Option<SortExpr> = None;
+        if self.lexer.info().token == Token::Sort {
+            sort = Some(self.sort()?);
+        };
+        let mut reverse: Option<InfoToken> = None;
+        if self.lexer.info().token == Token::Reverse {
+            reverse = Some(self.consume(Token::Reverse)?);
+        };
+        let mut separator: Option<SeparatorExpr> = None;
+        if self.lexer.info().token == Token::Separator {
+            separator = Some(self.separator()?);
+        };
+        self.misplaced_loop_clause()?;
         Ok(LoopStartExpr {
             left_mustache,
             r#loop,
             loop_variable,
-            array_accessor,
+            source,
             as_variable,
+            joins,
+            where_clause,
+            distinct,
+            group,
+            sort,
+            reverse,
+            separator,
             right_mustache: self.consume(Token::RightMustache)?,
         })
     }
 
+    /// Report a clause of a loop that is left over after all of the clauses are read, which
+    /// means that it is written out of order or more than once
+    fn misplaced_loop_clause(&mut self) -> Result<(), ParseError> {
+        let info = self.lexer.info().clone();
+        let is_clause = match info.token {
+            Token::Join
+            | Token::Where
+            | Token::Distinct
+            | Token::Sort
+            | Token::Reverse
+            | Token::Separator => true,
+            Token::Variable => info.slice.eq_ignore_ascii_case(GROUP_KEYWORD),
+            _ => false,
+        };
+        if !is_clause {
+            return Ok(());
+        }
+        Err(ParseError {
+            msg: format!(
+                "{} is out of place, the clauses of a LOOP must be written in the order JOIN, WHERE, DISTINCT, GROUP BY, SORT BY, REVERSE, SEPARATOR, and each one only once (except for JOIN)",
+                info.slice.to_uppercase()
+            ),
+            line_number: self.get_line_count_at_index(info.start),
+        })
+    }
+
+    /// Read what a loop goes through, which is a collection, a range of numbers, or any other
+    /// value that is checked to be a collection when the loop is interpreted
+    fn loop_source(&mut self) -> Result<LoopSourceExpr, ParseError> {
+        let start_token = self.lexer.info().clone();
+        let start = self.arithmetic()?;
+        if self.lexer.info().token == Token::DoubleDot {
+            let double_dot = self.consume(Token::DoubleDot)?;
+            let end_token = self.lexer.info().clone();
+            return Ok(LoopSourceExpr::Range(Box::new(RangeExpr {
+                start_token,
+                start,
+                double_dot,
+                end_token,
+                end: self.arithmetic()?,
+            })));
+        }
+        let filters = self.filters()?;
+        match start {
+            Expr::Slice(array_accessor) if filters.is_empty() => {
+                Ok(LoopSourceExpr::Collection(array_accessor))
+            }
+            Expr::Accessor(accessor) if filters.is_empty() => {
+                Ok(LoopSourceExpr::Collection(Box::new(ArrayAccessorExpr {
+                    variable: accessor.variable,
+                    indexes: accessor.indexes,
+                    array_slice: None,
+                })))
+            }
+            value => Ok(LoopSourceExpr::Value(Box::new(LoopValueExpr {
+                token: start_token,
+                value,
+                filters,
+            }))),
+        }
+    }
+
+    fn join(&mut self) -> Result<JoinExpr, ParseError> {
+        let join = self.consume(Token::Join)?;
+        let array_accessor = self.array_accessor()?;
+        let mut as_variable: Option<AsVariableExpr> = None;
+        if self.lexer.info().token == Token::As {
+            as_variable = Some(self.as_variable()?);
+        };
+        Ok(JoinExpr {
+            join,
+            array_accessor,
+            as_variable,
+            on: self.consume(Token::On)?,
+            condition: self.condition()?,
+        })
+    }
+
+    fn distinct(&mut self) -> Result<DistinctExpr, ParseError> {
+        let distinct = self.consume(Token::Distinct)?;
+        let by = match self.lexer.info().token {
+            Token::By => {
+                let by = self.consume(Token::By)?;
+                let token = self.lexer.info().clone();
+                Some(DistinctByExpr {
+                    by,
+                    token,
+                    key: self.condition()?,
+                })
+            }
+            _ => None,
+        };
+        Ok(DistinctExpr { distinct, by })
+    }
+
+    fn group(&mut self) -> Result<GroupExpr, ParseError> {
+        let group = self.consume(Token::Variable)?;
+        let by = self.consume(Token::By)?;
+        let token = self.lexer.info().clone();
+        let key = self.condition()?;
+        let r#as = self.consume(Token::As)?;
+        // The key can be named along with the items, as in AS (key, items)
+        if self.lexer.info().token == Token::LeftParentheses {
+            self.consume(Token::LeftParentheses)?;
+            let key_variable = Some(self.name()?);
+            self.consume(Token::Comma)?;
+            let variable = self.name()?;
+            self.consume(Token::RightParentheses)?;
+            return Ok(GroupExpr {
+                group,
+                by,
+                token,
+                key,
+                r#as,
+                key_variable,
+                variable,
+            });
+        }
+        Ok(GroupExpr {
+            group,
+            by,
+            token,
+            key,
+            r#as,
+            key_variable: None,
+            variable: self.name()?,
+        })
+    }
+
+    fn sort(&mut self) -> Result<SortExpr, ParseError> {
+        let sort = self.consume(Token::Sort)?;
+        let by = self.consume(Token::By)?;
+        let mut keys = vec![self.sort_key()?];
+        while self.lexer.info().token == Token::Comma {
+            self.consume(Token::Comma)?;
+            keys.push(self.sort_key()?);
+        }
+        Ok(SortExpr { sort, by, keys })
+    }
+
+    fn sort_key(&mut self) -> Result<SortKeyExpr, ParseError> {
+        let token = self.lexer.info().clone();
+        let value = self.condition()?;
+        let next = self.lexer.info().token.clone();
+        let direction = match next {
+            Token::Asc | Token::Desc => Some(self.consume(next)?),
+            _ => None,
+        };
+        let next = self.lexer.info().token.clone();
+        let ordering = match next {
+            Token::Numeric | Token::Lexical | Token::Natural => Some(self.consume(next)?),
+            _ => None,
+        };
+        Ok(SortKeyExpr {
+            token,
+            value,
+            direction,
+            ordering,
+        })
+    }
+
+    fn separator(&mut self) -> Result<SeparatorExpr, ParseError> {
+        Ok(SeparatorExpr {
+            separator: self.consume(Token::Separator)?,
+            value: self.consume(Token::StringLiteral)?,
+        })
+    }
+
     fn loop_variable(&mut self) -> Result<ParenVariableParenExpr, ParseError> {
         let left_paren = self.consume(Token::LeftParentheses)?;
-        let variable = self.consume(Token::Variable)?;
+        let variable = self.name()?;
         let second_variable = match self.lexer.info().token {
             Token::Comma => Some(self.comma_variable()?),
             _ => None,
@@ -219,14 +971,14 @@ impl<'a> Parser<'a> {
     fn comma_variable(&mut self) -> Result<CommaVariableExpr, ParseError> {
         Ok(CommaVariableExpr {
             comma: self.consume(Token::Comma)?,
-            variable: self.consume(Token::Variable)?,
+            variable: self.name()?,
         })
     }
 
     fn as_variable(&mut self) -> Result<AsVariableExpr, ParseError> {
         Ok(AsVariableExpr {
             r#as: self.consume(Token::As)?,
-            variable: self.consume(Token::Variable)?,
+            variable: self.name()?,
         })
     }
 
@@ -239,36 +991,31 @@ impl<'a> Parser<'a> {
     }
 
     fn array_accessor(&mut self) -> Result<ArrayAccessorExpr, ParseError> {
-        let variable = self.consume(Token::Variable)?;
-        let mut indexers: Vec<ArrayBracketExpr> = vec![];
-        while self.lexer.info().token == Token::LeftBracket || self.lexer.info().token == Token::Dot
-        {
-            if self.lexer.info().token == Token::LeftBracket {
-                let var_peek = self.lexer.peek();
-                match var_peek.token {
-                    Token::Variable | Token::DoubleDot | Token::Number => {
-                        let comma_peek = self.lexer.peek();
-                        if comma_peek.token == Token::Comma {
-                            break;
-                        };
+        let variable = match self.lexer.info().token {
+            Token::Loop => self.loop_detail()?,
+            _ => self.name()?,
+        };
+        let mut indexers: Vec<IndexExpr> = vec![];
+        let mut array_slice = None;
+        loop {
+            match self.lexer.info().token {
+                Token::Dot => indexers.push(IndexExpr::DotVariable(self.dot_variable()?)),
+                Token::LeftBracket => {
+                    // Both indexes and slices start with a bracket, a slice has a comma after its start
+                    let left_paren = self.consume(Token::LeftBracket)?;
+                    let start_index = self.array_slice_index()?;
+                    if self.lexer.info().token == Token::Comma {
+                        array_slice = Some(self.array_slice(left_paren, start_index)?);
+                        break;
                     }
-                    _ => (),
-                };
-
-                indexers.push(self.array_bracket()?);
+                    indexers.push(IndexExpr::ArrayBracket(
+                        self.array_bracket(left_paren, start_index)?,
+                    ));
+                }
+                _ => break,
             };
-
-            // if self.lexer.info().token == Token::Dot {
-            //     indexers.push(self.dot_variable());
-            // };
         }
 
-        let array_slice = if self.lexer.info().token == Token::LeftBracket {
-            Some(self.array_slice()?)
-        } else {
-            None
-        };
-
         Ok(ArrayAccessorExpr {
             variable,
             indexes: indexers,
@@ -276,76 +1023,113 @@ impl<'a> Parser<'a> {
         })
     }
 
-    fn accessor(&mut self) -> Result<AccessorExpr, ParseError> {
-        let variable = self.consume(Token::Variable)?;
-        let mut indexers: Vec<ArrayBracketExpr> = vec![];
-        while self.lexer.info().token == Token::LeftBracket || self.lexer.info().token == Token::Dot
-        {
-            if self.lexer.info().token == Token::LeftBracket {
-                indexers.push(self.array_bracket()?);
-            };
-
-            // if self.lexer.info().token == Token::Dot {
-            //     indexers.push(self.dot_variable());
-            // };
-        }
+    /// Read a detail of the current loop, such as `loop.first`, as a single name. Since `LOOP`
+    /// is a keyword, these names can never be taken by the variables of a template
+    fn loop_detail(&mut self) -> Result<InfoToken, ParseError> {
+        let start = self.consume(Token::Loop)?.start;
+        self.consume(Token::Dot)?;
+        let name = self.identifier()?;
+        Ok(InfoToken {
+            token: Token::Variable,
+            slice: format!("loop.{}", name.slice),
+            start,
+            end: name.end,
+        })
+    }
 
-        Ok(AccessorExpr {
-            variable,
-            indexes: indexers,
+    fn dot_variable(&mut self) -> Result<DotVariableExpr, ParseError> {
+        Ok(DotVariableExpr {
+            dot: self.consume(Token::Dot)?,
+            variable: self.identifier()?,
         })
     }
 
-    // fn dot_variable(&mut self) -> Expr {
-    //     Expr::DotVariable(DotVariableExpr {
-    //         dot: self.consume(Token::Dot),
-    //         variable: self.consume(Token::Variable),
-    //     })
-    // }
+    /// Read the name of a variable. Only `LOOP`, `OUTPUT`, `IMPORT`, `END`, `AS` and
+    /// `DELIMITERS` are reserved, the other keywords can be names wherever a name is expected
+    fn name(&mut self) -> Result<InfoToken, ParseError> {
+        let token = self.lexer.info().token.clone();
+        if !is_contextual_keyword(&token) {
+            return self.consume(Token::Variable);
+        }
+        let mut name = self.consume(token)?;
+        name.token = Token::Variable;
+        Ok(name)
+    }
+
+    /// Read a name that is allowed to be a keyword, such as the name of a column or a filter
+    fn identifier(&mut self) -> Result<InfoToken, ParseError> {
+        let info = self.lexer.info().clone();
+        let is_name = info.token != Token::Number
+            && !info.slice.is_empty()
+            && info.slice.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !is_name {
+            return self.consume(Token::Variable);
+        }
+        let mut name = self.consume(info.token)?;
+        name.token = Token::Variable;
+        Ok(name)
+    }
 
-    fn array_slice(&mut self) -> Result<ArraySliceExpr, ParseError> {
+    fn array_slice(
+        &mut self,
+        left_paren: InfoToken,
+        start_index: ArraySliceIndexExpr,
+    ) -> Result<ArraySliceExpr, ParseError> {
+        let comma = self.consume(Token::Comma)?;
+        let end_index = self.array_slice_index()?;
+        let step = match self.lexer.info().token {
+            Token::Comma => Some(ArraySliceStepExpr {
+                comma: self.consume(Token::Comma)?,
+                step_index: self.array_slice_index()?,
+            }),
+            _ => None,
+        };
         Ok(ArraySliceExpr {
-            left_paren: self.consume(Token::LeftBracket)?,
-            start_index: self.array_slice_index()?,
-            comma: self.consume(Token::Comma)?,
-            end_index: self.array_slice_index()?,
+            left_paren,
+            start_index,
+            comma,
+            end_index,
+            step,
             right_paren: self.consume(Token::RightBracket)?,
         })
     }
 
     fn array_slice_index(&mut self) -> Result<ArraySliceIndexExpr, ParseError> {
-        match self.lexer.info().token {
+        let token = self.lexer.info().clone();
+        match token.token {
             Token::DoubleDot => Ok(ArraySliceIndexExpr {
                 token: self.consume(Token::DoubleDot)?,
-            }),
-            Token::Variable => Ok(ArraySliceIndexExpr {
-                token: self.consume(Token::Variable)?,
+                index: None,
             }),
             _ => Ok(ArraySliceIndexExpr {
-                token: self.consume(Token::Number)?,
+                token,
+                index: Some(self.arithmetic()?),
             }),
         }
     }
 
-    fn array_bracket(&mut self) -> Result<ArrayBracketExpr, ParseError> {
+    fn array_bracket(
+        &mut self,
+        left_paren: InfoToken,
+        index: ArraySliceIndexExpr,
+    ) -> Result<ArrayBracketExpr, ParseError> {
+        let token = index.token;
+        let index = match index.index {
+            Some(index) => index,
+            None => {
+                return Err(ParseError {
+                    msg: String::from("Only slices can use .., as in [1, ..]"),
+                    line_number: self.current_line,
+                })
+            }
+        };
         Ok(ArrayBracketExpr {
-            left_paren: self.consume(Token::LeftBracket)?,
-            variable: self.array_bracket_index()?,
+            left_paren,
+            variable: ArrayBracketIndexExpr { token, index },
             right_paren: self.consume(Token::RightBracket)?,
         })
     }
 
-    fn array_bracket_index(&mut self) -> Result<ArrayBracketIndexExpr, ParseError> {
-        match self.lexer.info().token {
-            Token::Variable => Ok(ArrayBracketIndexExpr {
-                token: self.consume(Token::Variable)?,
-            }),
-            _ => Ok(ArrayBracketIndexExpr {
-                token: self.consume(Token::Number)?,
-            }),
-        }
-    }
-
     fn consume(&mut self, next: Token) -> Result<InfoToken, ParseError> {
         // println!("{:?}", next);
         let start_index = self.lexer.info().start;
@@ -372,6 +1156,82 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Whether `token` is a keyword that only has a meaning in some places, so that it can
+/// be used as a name everywhere else
+fn is_contextual_keyword(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Escape
+            | Token::Define
+            | Token::Call
+            | Token::Extends
+            | Token::Block
+            | Token::Include
+            | Token::Raw
+            | Token::EndRaw
+            | Token::Set
+            | Token::Mode
+            | Token::Separator
+            | Token::Join
+            | Token::On
+            | Token::Where
+            | Token::Distinct
+            | Token::Sort
+            | Token::By
+            | Token::Asc
+            | Token::Desc
+            | Token::Numeric
+            | Token::Lexical
+            | Token::Natural
+            | Token::Reverse
+            | Token::If
+            | Token::Else
+            | Token::And
+            | Token::Or
+            | Token::Not
+            | Token::Is
+            | Token::Empty
+    )
+}
+
+/// Whether `token` can only come after a value, which means that a keyword before it is
+/// used as a name, as in `{{ set | upper }}` or `not == 1`
+fn follows_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::RightMustache
+            | Token::Pipe
+            | Token::Dot
+            | Token::LeftBracket
+            | Token::RightBracket
+            | Token::RightParentheses
+            | Token::Comma
+            | Token::Assign
+            | Token::Equal
+            | Token::NotEqual
+            | Token::LessThan
+            | Token::LessThanOrEqual
+            | Token::GreaterThan
+            | Token::GreaterThanOrEqual
+            | Token::Plus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent
+            | Token::And
+            | Token::Or
+            | Token::Is
+    )
+}
+
+/// Whether the keyword that starts a tag is a statement, given the token after it. The
+/// path of an `INCLUDE` or `EXTENDS` can start with any character, as in `../base.html`
+fn starts_statement(keyword: &Token, next: &Token) -> bool {
+    match keyword {
+        Token::Include | Token::Extends => *next != Token::RightMustache && *next != Token::Pipe,
+        _ => !follows_operand(next),
+    }
+}
+
 pub struct ParseError {
     line_number: usize,
     pub msg: String,
@@ -1,9 +1,23 @@
 #[derive(Clone)]
 pub enum VarType {
-    Number(Var<usize>),
+    Number(Var<i64>),
     Value(Var<String>),
     Row(Var<Vec<String>>),
     Table(Var<Vec<Vec<String>>>),
+    Bool(Var<bool>),
+}
+
+impl VarType {
+    /// Whether this value holds nothing, an empty string or an empty collection
+    pub fn is_empty(&self) -> bool {
+        match self {
+            VarType::Number(_) => false,
+            VarType::Value(var) => var.data.is_empty(),
+            VarType::Row(var) => var.data.is_empty(),
+            VarType::Table(var) => var.data.is_empty(),
+            VarType::Bool(var) => !var.data,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -11,6 +25,7 @@ pub struct Var<T> {
     pub row: usize,
     pub col: usize,
     pub data: T,
+    pub headers: Option<Vec<String>>,
 }
 
 impl<T> Var<T> {
@@ -19,6 +34,25 @@ impl<T> Var<T> {
             row: 0,
             col: 0,
             data,
+            headers: None,
+        }
+    }
+
+    /// Construct a new `Var` whose columns can also be accessed by name
+    pub fn with_headers(data: T, headers: Option<Vec<String>>) -> Var<T> {
+        Var {
+            row: 0,
+            col: 0,
+            data,
+            headers,
+        }
+    }
+
+    /// Find the index of the column with the given name
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        match &self.headers {
+            Some(headers) => headers.iter().position(|header| header.trim() == name),
+            None => None,
         }
     }
 }
@@ -14,9 +14,102 @@ pub enum Token {
     #[regex = "(?i)end"]
     End,
 
+    #[regex = "(?i)escape"]
+    Escape,
+
+    #[regex = "(?i)delimiters"]
+    Delimiters,
+
+    #[regex = "(?i)define"]
+    Define,
+
+    #[regex = "(?i)call"]
+    Call,
+
+    #[regex = "(?i)extends"]
+    Extends,
+
+    #[regex = "(?i)block"]
+    Block,
+
+    #[regex = "(?i)include"]
+    Include,
+
+    #[regex = "(?i)raw"]
+    Raw,
+
+    #[regex = "(?i)endraw"]
+    EndRaw,
+
+    #[regex = "(?i)set"]
+    Set,
+
+    #[regex = "(?i)mode"]
+    Mode,
+
     #[regex = "(?i)as"]
     As,
 
+    #[regex = "(?i)separator"]
+    Separator,
+
+    #[regex = "(?i)join"]
+    Join,
+
+    #[regex = "(?i)on"]
+    On,
+
+    #[regex = "(?i)where"]
+    Where,
+
+    #[regex = "(?i)distinct"]
+    Distinct,
+
+    #[regex = "(?i)sort"]
+    Sort,
+
+    #[regex = "(?i)by"]
+    By,
+
+    #[regex = "(?i)asc"]
+    Asc,
+
+    #[regex = "(?i)desc"]
+    Desc,
+
+    #[regex = "(?i)numeric"]
+    Numeric,
+
+    #[regex = "(?i)lexical"]
+    Lexical,
+
+    #[regex = "(?i)natural"]
+    Natural,
+
+    #[regex = "(?i)reverse"]
+    Reverse,
+
+    #[regex = "(?i)if"]
+    If,
+
+    #[regex = "(?i)else"]
+    Else,
+
+    #[regex = "(?i)and"]
+    And,
+
+    #[regex = "(?i)or"]
+    Or,
+
+    #[regex = "(?i)not"]
+    Not,
+
+    #[regex = "(?i)is"]
+    Is,
+
+    #[regex = "(?i)empty"]
+    Empty,
+
     #[token = "("]
     LeftParentheses,
 
@@ -35,6 +128,10 @@ pub enum Token {
     #[token = "}}"]
     RightMustache,
 
+    Text,
+
+    Comment,
+
     #[token = ".."]
     DoubleDot,
 
@@ -50,6 +147,48 @@ pub enum Token {
     #[token = ";"]
     SemiColon,
 
+    #[token = "|"]
+    Pipe,
+
+    #[token = "+"]
+    Plus,
+
+    #[token = "-"]
+    Minus,
+
+    #[token = "*"]
+    Star,
+
+    #[token = "/"]
+    Slash,
+
+    #[token = "%"]
+    Percent,
+
+    #[token = "="]
+    Assign,
+
+    #[token = "=="]
+    Equal,
+
+    #[token = "!="]
+    NotEqual,
+
+    #[token = "<"]
+    LessThan,
+
+    #[token = "<="]
+    LessThanOrEqual,
+
+    #[token = ">"]
+    GreaterThan,
+
+    #[token = ">="]
+    GreaterThanOrEqual,
+
+    #[regex = "\"([^\"\\\\\n]|\\\\.)*\""]
+    StringLiteral,
+
     #[regex = "[0-9]+"]
     Number,
 
@@ -71,28 +210,80 @@ pub struct InfoToken {
     pub end: usize,
 }
 
+const DEFAULT_LEFT_MUSTACHE: &str = "{{";
+const DEFAULT_RIGHT_MUSTACHE: &str = "}}";
+const DELIMITERS_KEYWORD: &str = "delimiters";
+const TRIM_MARKER: &str = "-";
+const COMMENT_MARKER: &str = "#";
+const LITERAL_MUSTACHE_MARKER: &str = "\\";
+
 pub struct Tokenizer<'a> {
-    lexer: logos::Lexer<Token, &'a str>,
+    text: &'a str,
+    lexer: logos::Lexer<Token, &'a [u8]>,
+    lexer_offset: usize,
+    lexer_info: InfoToken,
+    is_template: bool,
+    left_mustache: String,
+    right_mustache: String,
+    next_delimiters: Option<(String, String)>,
+    directive_arguments: Vec<InfoToken>,
+    in_mustache: bool,
+    in_raw: bool,
+    is_literal_mustache_next: bool,
+    position: usize,
+    mustache_content_end: usize,
+    mustache_end: usize,
+    trim_mustache_end: bool,
+    trim_next_text: bool,
     peeks: Vec<InfoToken>,
     peek_index: usize,
     current_info: InfoToken,
 }
 
 impl<'a> Tokenizer<'a> {
-    /// Construct a new `Tokenizer` with source text
+    /// Construct a new `Tokenizer` that reads the whole source text as tokens
     pub fn new(text: &'a str) -> Tokenizer {
-        let lexer = Token::lexer(text);
-        Tokenizer {
+        Tokenizer::with_mode(text, false)
+    }
+
+    /// Construct a new `Tokenizer` for a template. Only the content of mustaches is read
+    /// as tokens, everything else is returned as `Token::Text`, exactly as it is written
+    pub fn from_template(text: &'a str) -> Tokenizer<'a> {
+        Tokenizer::with_mode(text, true)
+    }
+
+    fn with_mode(text: &'a str, is_template: bool) -> Tokenizer<'a> {
+        let lexer = Token::lexer(if is_template { &[] } else { text.as_bytes() });
+        let empty_info = InfoToken {
+            token: Token::EOF,
+            slice: String::from(""),
+            start: 0,
+            end: 0,
+        };
+        let mut tokenizer = Tokenizer {
+            text,
+            lexer,
+            lexer_offset: 0,
+            lexer_info: empty_info.clone(),
+            is_template,
+            left_mustache: String::from(DEFAULT_LEFT_MUSTACHE),
+            right_mustache: String::from(DEFAULT_RIGHT_MUSTACHE),
+            next_delimiters: None,
+            directive_arguments: vec![],
+            in_mustache: !is_template,
+            in_raw: false,
+            is_literal_mustache_next: false,
+            position: 0,
+            mustache_content_end: text.len(),
+            mustache_end: text.len(),
+            trim_mustache_end: false,
+            trim_next_text: false,
             peeks: vec![],
             peek_index: 1,
-            current_info: InfoToken {
-                token: lexer.token.clone(),
-                slice: lexer.slice().to_string(),
-                start: lexer.range().start,
-                end: lexer.range().end,
-            },
-            lexer: lexer,
-        }
+            current_info: empty_info,
+        };
+        tokenizer.advance_lexer();
+        tokenizer
     }
 
     /// Return the information of the `Token` that this `Tokenizer` is current;y sitting on
@@ -108,15 +299,15 @@ impl<'a> Tokenizer<'a> {
 
     fn advance_handle_variable(&mut self) -> &InfoToken {
         let mut variables = vec![];
-        let mut text = String::from(self.lexer.slice());
+        let mut text = self.lexer_info.slice.clone();
         variables.push(self.get_info_at_lexer());
-        self.lexer.advance();
-        while self.lexer.token == Token::Variable
-            && self.lexer.range().start == variables.last().expect("Variables is empty").end
+        self.advance_lexer();
+        while self.lexer_info.token == Token::Variable
+            && self.lexer_info.start == variables.last().expect("Variables is empty").end
         {
-            text = format!("{}{}", text, self.lexer.slice());
+            text = format!("{}{}", text, self.lexer_info.slice);
             variables.push(self.get_info_at_lexer());
-            self.lexer.advance();
+            self.advance_lexer();
         }
         let end = variables.last().expect("Variables is empty").end;
         let start = variables.first().expect("Variables is empty").start;
@@ -137,11 +328,290 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn get_info_at_lexer(&self) -> InfoToken {
+        self.lexer_info.clone()
+    }
+
+    fn advance_lexer(&mut self) {
+        self.lexer_info = if self.in_mustache {
+            self.next_info_in_mustache()
+        } else {
+            self.next_info_in_text()
+        };
+    }
+
+    fn next_info_in_mustache(&mut self) -> InfoToken {
+        if self.lexer.token != Token::EOF || !self.is_template {
+            let mut info = self.lexer_token();
+            self.advance_lexer_past(info.end);
+            // The lexer stops after a keyword that starts a name, as in `settings`,
+            // so the rest of the name is joined back to it
+            let starts_name = info
+                .slice
+                .starts_with(|c: char| c.is_alphabetic() || c == '_');
+            while starts_name && is_part_of_name(&info.slice) && self.lexer.token != Token::EOF {
+                let next = self.lexer_token();
+                if next.start != info.end || !is_part_of_name(&next.slice) {
+                    break;
+                }
+                info.token = Token::Variable;
+                info.slice.push_str(&next.slice);
+                info.end = next.end;
+                self.advance_lexer_past(next.end);
+            }
+            return info;
+        }
+        if !self.directive_arguments.is_empty() {
+            return self.directive_arguments.remove(0);
+        }
+
+        self.in_mustache = false;
+        if self.mustache_end > self.text.len() {
+            self.position = self.text.len();
+            return self.info_at(Token::EOF, self.text.len(), self.text.len());
+        }
+        self.position = self.mustache_end;
+        self.trim_next_text = self.trim_mustache_end;
+        if let Some((left_mustache, right_mustache)) = self.next_delimiters.take() {
+            self.left_mustache = left_mustache;
+            self.right_mustache = right_mustache;
+        }
+        self.info_at(
+            Token::RightMustache,
+            self.mustache_content_end,
+            self.mustache_end,
+        )
+    }
+
+    /// Return the token the lexer is sitting on. The lexer reads the text as bytes, so a
+    /// character that is not ASCII comes out as errors on each of its bytes. Such a token is
+    /// widened to the whole character, and a letter is read as part of a name
+    fn lexer_token(&self) -> InfoToken {
+        let start = self.lexer.range().start + self.lexer_offset;
+        let mut end = self.lexer.range().end + self.lexer_offset;
+        while !self.text.is_char_boundary(end) {
+            end += 1;
+        }
+        let mut info = self.info_at(self.lexer.token.clone(), start, end);
+        if info.token == Token::Error && is_part_of_name(&info.slice) {
+            info.token = Token::Variable;
+        }
+        info
+    }
+
+    /// Advance the lexer until it sits on a token that starts at or after `end`
+    fn advance_lexer_past(&mut self, end: usize) {
+        self.lexer.advance();
+        while self.lexer.token != Token::EOF && self.lexer.range().start + self.lexer_offset < end {
+            self.lexer.advance();
+        }
+    }
+
+    fn next_info_in_text(&mut self) -> InfoToken {
+        loop {
+            if self.position >= self.text.len() {
+                return self.info_at(Token::EOF, self.text.len(), self.text.len());
+            }
+
+            let end = self.text_end();
+            let is_literal_mustache = self.text[end..].starts_with(LITERAL_MUSTACHE_MARKER);
+            if end == self.position && !is_literal_mustache {
+                if let Some(content_start) = self.comment_content_start() {
+                    return self.comment(content_start);
+                }
+                return self.enter_mustache();
+            }
+            let mut start = self.position;
+            self.position = end;
+            if is_literal_mustache {
+                self.position += LITERAL_MUSTACHE_MARKER.len();
+                self.is_literal_mustache_next = true;
+            }
+            if self.trim_next_text {
+                self.trim_next_text = false;
+                start = end - self.text[start..end].trim_start().len();
+            }
+            let mut text_end = end;
+            let is_trimmed = match self.text[end..].strip_prefix(self.left_mustache.as_str()) {
+                Some(content) => starts_with_trim_marker(content),
+                None => false,
+            };
+            if is_trimmed {
+                text_end = start + self.text[start..end].trim_end().len();
+            }
+
+            if start < text_end {
+                return self.info_at(Token::Text, start, text_end);
+            }
+        }
+    }
+
+    /// Find where the text starting at the current position ends. Inside of a RAW block,
+    /// the text only ends at the ENDRAW that closes the block
+    fn text_end(&mut self) -> usize {
+        let mut index = self.position;
+        while let Some(offset) = self.text[index..].find(self.left_mustache.as_str()) {
+            let mustache_start = index + offset;
+            index = mustache_start + self.left_mustache.len();
+            if self.in_raw {
+                if self.is_raw_end(mustache_start) {
+                    return mustache_start;
+                }
+            } else if mustache_start == self.position && self.is_literal_mustache_next {
+                self.is_literal_mustache_next = false;
+            } else if self.text[..mustache_start].ends_with(LITERAL_MUSTACHE_MARKER) {
+                return mustache_start - LITERAL_MUSTACHE_MARKER.len();
+            } else {
+                return mustache_start;
+            }
+        }
+        self.text.len()
+    }
+
+    fn is_raw_end(&self, mustache_start: usize) -> bool {
+        let mut content = &self.text[mustache_start + self.left_mustache.len()..];
+        content = content.strip_prefix(TRIM_MARKER).unwrap_or(content);
+        let content = match content.find(self.right_mustache.as_str()) {
+            Some(index) => &content[..index],
+            None => return false,
+        };
+        let content = content.strip_suffix(TRIM_MARKER).unwrap_or(content);
+        content.trim().eq_ignore_ascii_case("endraw")
+    }
+
+    /// Return where the content of the comment starting at the current position begins,
+    /// or `None` if the mustache at the current position is not a comment
+    fn comment_content_start(&self) -> Option<usize> {
+        let mut index = self.position + self.left_mustache.len();
+        if starts_with_trim_marker(&self.text[index..]) {
+            index += TRIM_MARKER.len();
+        }
+        if self.text[index..].starts_with(COMMENT_MARKER) {
+            Some(index + COMMENT_MARKER.len())
+        } else {
+            None
+        }
+    }
+
+    /// Read a whole comment as a single `Token::Comment`, which may span multiple lines.
+    /// A comment that is never closed is returned as a `Token::Error` on its opening mustache
+    fn comment(&mut self, content_start: usize) -> InfoToken {
+        let start = self.position;
+        let comment_end = format!("{}{}", COMMENT_MARKER, self.right_mustache);
+        match self.text[content_start..].find(&comment_end) {
+            Some(index) => {
+                let content = &self.text[content_start..content_start + index];
+                self.trim_next_text = content.ends_with(TRIM_MARKER);
+                self.position = content_start + index + comment_end.len();
+                self.info_at(Token::Comment, start, self.position)
+            }
+            None => {
+                self.position = self.text.len();
+                self.info_at(Token::Error, start, content_start)
+            }
+        }
+    }
+
+    fn enter_mustache(&mut self) -> InfoToken {
+        let start = self.position;
+        let mut content_start = start + self.left_mustache.len();
+        if starts_with_trim_marker(&self.text[content_start..]) {
+            content_start += TRIM_MARKER.len();
+        }
+        self.find_mustache_end(content_start);
+        self.in_raw = self.text[content_start..self.mustache_content_end]
+            .trim()
+            .eq_ignore_ascii_case("raw");
+        let lexer_end = self.delimiters_directive(content_start);
+        self.lexer = Token::lexer(&self.text.as_bytes()[content_start..lexer_end]);
+        self.lexer_offset = content_start;
+        self.in_mustache = true;
+        self.position = content_start;
+        self.info_at(Token::LeftMustache, start, content_start)
+    }
+
+    /// Check if the mustache whose content starts at `content_start` changes the delimiters.
+    /// The new delimiters can be any text, so they are returned as `Token::Text` after the
+    /// keyword, and only the part of the mustache before them is read by the lexer
+    fn delimiters_directive(&mut self, content_start: usize) -> usize {
+        let content = &self.text[content_start..self.mustache_content_end];
+        let keyword_start = content_start + content.len() - content.trim_start().len();
+        let keyword_end = keyword_start + DELIMITERS_KEYWORD.len();
+        let is_directive = keyword_end < self.mustache_content_end
+            && self.text.is_char_boundary(keyword_end)
+            && self.text[keyword_start..keyword_end].eq_ignore_ascii_case(DELIMITERS_KEYWORD)
+            && self.text[keyword_end..].starts_with(char::is_whitespace);
+        if !is_directive {
+            return self.mustache_content_end;
+        }
+
+        self.directive_arguments = self.words(keyword_end, self.mustache_content_end);
+        if let [left_mustache, right_mustache] = self.directive_arguments.as_slice() {
+            self.next_delimiters =
+                Some((left_mustache.slice.clone(), right_mustache.slice.clone()));
+        }
+        keyword_end
+    }
+
+    /// Split the text between `start` and `end` on whitespace
+    fn words(&self, start: usize, end: usize) -> Vec<InfoToken> {
+        let mut words = vec![];
+        let mut word_start = None;
+        for (index, character) in self.text[start..end].char_indices() {
+            match (word_start, character.is_whitespace()) {
+                (None, false) => word_start = Some(start + index),
+                (Some(word), true) => {
+                    words.push(self.info_at(Token::Text, word, start + index));
+                    word_start = None;
+                }
+                _ => (),
+            };
+        }
+        if let Some(word) = word_start {
+            words.push(self.info_at(Token::Text, word, end));
+        }
+        words
+    }
+
+    /// Find where the mustache whose content starts at `content_start` is closed,
+    /// skipping over string literals that might contain a closing mustache
+    fn find_mustache_end(&mut self, content_start: usize) {
+        let bytes = self.text.as_bytes();
+        let mut index = content_start;
+        let mut in_string = false;
+        while index < bytes.len() {
+            if in_string {
+                match bytes[index] {
+                    b'\\' => index += 1,
+                    b'"' | b'\n' => in_string = false,
+                    _ => (),
+                };
+            } else if bytes[index] == b'"' {
+                in_string = true;
+            } else if bytes[index..].starts_with(self.right_mustache.as_bytes()) {
+                let trimmed =
+                    index > content_start && self.text[content_start..index].ends_with(TRIM_MARKER);
+                self.trim_mustache_end = trimmed;
+                self.mustache_content_end = if trimmed {
+                    index - TRIM_MARKER.len()
+                } else {
+                    index
+                };
+                self.mustache_end = index + self.right_mustache.len();
+                return;
+            }
+            index += 1;
+        }
+        self.trim_mustache_end = false;
+        self.mustache_content_end = self.text.len();
+        self.mustache_end = self.text.len() + 1;
+    }
+
+    fn info_at(&self, token: Token, start: usize, end: usize) -> InfoToken {
         InfoToken {
-            token: self.lexer.token.clone(),
-            slice: self.lexer.slice().to_string(),
-            start: self.lexer.range().start,
-            end: self.lexer.range().end,
+            token,
+            slice: self.text[start..end].to_string(),
+            start,
+            end,
         }
     }
 
@@ -149,12 +619,17 @@ impl<'a> Tokenizer<'a> {
     pub fn advance(&mut self) {
         self.peek_index = 1;
         if self.peeks.len() == 0 {
-            self.lexer.advance();
+            self.advance_lexer();
         } else {
             self.peeks.remove(0);
         }
     }
 
+    /// Make the next call to `peek()` return the token right after the current one again
+    pub fn reset_peek(&mut self) {
+        self.peek_index = 1;
+    }
+
     /// Keep this `Tokenizer` sitting where it is, but return the next token.
     /// Calling peek multiple times in succession will return the token that is
     /// after the last peek. For example, if there is Token1, Token2, and Token3,
@@ -169,12 +644,26 @@ impl<'a> Tokenizer<'a> {
             self.get_current_info()
         } else {
             self.peeks.push(self.get_info_at_lexer());
-            self.lexer.advance();
+            self.advance_lexer();
             self.get_current_info()
         }
     }
 }
 
+/// Whether all of `slice` can be part of the name of a variable
+fn is_part_of_name(slice: &str) -> bool {
+    !slice.is_empty() && slice.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Check if `text` starts with the marker that trims the whitespace before a tag. The marker
+/// has to be followed by whitespace or a comment, so that `{{-1}}` is read as a negative number
+fn starts_with_trim_marker(text: &str) -> bool {
+    match text.strip_prefix(TRIM_MARKER) {
+        Some(rest) => rest.starts_with(char::is_whitespace) || rest.starts_with(COMMENT_MARKER),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,4 +746,119 @@ mod tests {
         assert_eq!(tokenizer.info().token, Token::EOF);
         assert_eq!(tokenizer.peek().token, Token::EOF);
     }
+
+    #[test]
+    fn names_that_start_with_keywords_are_variables() {
+        let mut tokenizer = Tokenizer::from_template("{{ settings sort_by SORT BY }}");
+        assert_eq!(tokenizer.peek().token, Token::Variable);
+        let info = tokenizer.peek().clone();
+        assert_eq!(
+            (info.token, info.slice.as_str()),
+            (Token::Variable, "sort_by")
+        );
+        assert_eq!(tokenizer.peek().token, Token::Sort);
+        assert_eq!(tokenizer.peek().token, Token::By);
+    }
+
+    #[test]
+    fn template_text_is_kept_as_is() {
+        let mut tokenizer = Tokenizer::from_template("a as \"{{ loop }}\"\n");
+        assert_eq!(tokenizer.info().token, Token::Text);
+        assert_eq!(tokenizer.info().slice, "a as \"");
+        assert_eq!(tokenizer.peek().token, Token::LeftMustache);
+        assert_eq!(tokenizer.peek().token, Token::Loop);
+        assert_eq!(tokenizer.peek().token, Token::RightMustache);
+        assert_eq!(tokenizer.peek().slice, "\"\n");
+        assert_eq!(tokenizer.peek().token, Token::EOF);
+    }
+
+    #[test]
+    fn trim_markers_remove_surrounding_whitespace() {
+        let mut tokenizer = Tokenizer::from_template("a \n {{- \"}}\" -}} \n b");
+        assert_eq!(tokenizer.info().slice, "a");
+        assert_eq!(tokenizer.peek().slice, "{{-");
+        assert_eq!(tokenizer.peek().token, Token::StringLiteral);
+        assert_eq!(tokenizer.peek().slice, "-}}");
+        assert_eq!(tokenizer.peek().slice, "b");
+    }
+
+    #[test]
+    fn comments_are_read_as_one_token() {
+        let mut tokenizer = Tokenizer::from_template("a{{# {{ LOOP }}\n}} #}}b {{-# -#}} c{{# d");
+        assert_eq!(tokenizer.info().slice, "a");
+        assert_eq!(tokenizer.peek().token, Token::Comment);
+        assert_eq!(tokenizer.peek().slice, "b");
+        assert_eq!(tokenizer.peek().slice, "{{-# -#}}");
+        assert_eq!(tokenizer.peek().slice, "c");
+        assert_eq!(tokenizer.peek().token, Token::Error);
+        assert_eq!(tokenizer.peek().token, Token::EOF);
+    }
+
+    #[test]
+    fn delimiters_are_changed_for_the_rest_of_the_text() {
+        let mut tokenizer = Tokenizer::from_template("{{ DELIMITERS <% %> }}{{ a }}<%- b %>");
+        assert_eq!(tokenizer.peek().token, Token::Delimiters);
+        assert_eq!(tokenizer.peek().slice, "<%");
+        assert_eq!(tokenizer.peek().slice, "%>");
+        assert_eq!(tokenizer.peek().token, Token::RightMustache);
+        assert_eq!(tokenizer.peek().slice, "{{ a }}");
+        assert_eq!(tokenizer.peek().slice, "<%-");
+        assert_eq!(tokenizer.peek().token, Token::Variable);
+        assert_eq!(tokenizer.peek().slice, "%>");
+        assert_eq!(tokenizer.peek().token, Token::EOF);
+    }
+
+    #[test]
+    fn raw_blocks_are_read_as_text() {
+        let mut tokenizer = Tokenizer::from_template(
+            "{{ RAW }}{{ a }}{{# b #}}{{ end }}{{ ENDRAW }}\\{{ c }}\\{{-{{ end }}",
+        );
+        assert_eq!(tokenizer.peek().token, Token::Raw);
+        assert_eq!(tokenizer.peek().token, Token::RightMustache);
+        assert_eq!(tokenizer.peek().slice, "{{ a }}{{# b #}}{{ end }}");
+        assert_eq!(tokenizer.peek().token, Token::LeftMustache);
+        assert_eq!(tokenizer.peek().token, Token::EndRaw);
+        assert_eq!(tokenizer.peek().token, Token::RightMustache);
+        assert_eq!(tokenizer.peek().slice, "{{ c }}");
+        assert_eq!(tokenizer.peek().slice, "{{-");
+        assert_eq!(tokenizer.peek().token, Token::LeftMustache);
+    }
+
+    #[test]
+    fn text_that_is_not_ascii_can_be_inside_mustaches() {
+        let mut tokenizer = Tokenizer::from_template("{{ row.é sortie_é \"}}é\" € }}ü");
+        assert_eq!(tokenizer.peek().token, Token::Variable);
+        assert_eq!(tokenizer.peek().token, Token::Dot);
+        assert_eq!(tokenizer.peek().slice, "é");
+        let info = tokenizer.peek().clone();
+        assert_eq!(
+            (info.token, info.slice.as_str()),
+            (Token::Variable, "sortie_é")
+        );
+        assert_eq!(tokenizer.peek().slice, "\"}}é\"");
+        let info = tokenizer.peek().clone();
+        assert_eq!((info.token, info.slice.as_str()), (Token::Error, "€"));
+        assert_eq!(tokenizer.peek().token, Token::RightMustache);
+        assert_eq!(tokenizer.peek().slice, "ü");
+    }
+
+    #[test]
+    fn delimiters_can_be_characters_that_are_not_ascii() {
+        let mut tokenizer =
+            Tokenizer::from_template("{{ DELIMITERS é ü }}é a ü é- b -ü c é# d #ü e");
+        assert_eq!(tokenizer.peek().token, Token::Delimiters);
+        assert_eq!(tokenizer.peek().slice, "é");
+        assert_eq!(tokenizer.peek().slice, "ü");
+        assert_eq!(tokenizer.peek().token, Token::RightMustache);
+        assert_eq!(tokenizer.peek().slice, "é");
+        assert_eq!(tokenizer.peek().slice, "a");
+        assert_eq!(tokenizer.peek().slice, "ü");
+        assert_eq!(tokenizer.peek().slice, "é-");
+        assert_eq!(tokenizer.peek().slice, "b");
+        assert_eq!(tokenizer.peek().slice, "-ü");
+        assert_eq!(tokenizer.peek().slice, "c ");
+        assert_eq!(tokenizer.peek().token, Token::Comment);
+        assert_eq!(tokenizer.peek().slice, " e");
+        assert_eq!(tokenizer.peek().token, Token::EOF);
+    }
 }
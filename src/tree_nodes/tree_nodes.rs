@@ -6,12 +6,29 @@ pub enum Expr {
     Block(Box<BlockExpr>),
     Anything(Box<AnythingExpr>),
     Loop(Box<LoopExpr>),
-    MustacheAccessor(MustacheAccessorExpr),
+    Raw(Box<RawExpr>),
+    Include(Box<IncludeExpr>),
+    Define(Box<DefineExpr>),
+    Call(Box<CallExpr>),
+    Extends(Box<ExtendsExpr>),
+    NamedBlock(Box<NamedBlockExpr>),
+    MustacheAccessor(Box<MustacheAccessorExpr>),
+    If(Box<IfExpr>),
+    Binary(Box<BinaryExpr>),
+    Unary(Box<UnaryExpr>),
+    IsEmpty(Box<IsEmptyExpr>),
+    Literal(LiteralExpr),
+    Accessor(AccessorExpr),
+    Slice(Box<ArrayAccessorExpr>),
+    Function(Box<FunctionExpr>),
+    Set(Box<SetExpr>),
+    Mode(Box<ModeExpr>),
 }
 
 #[derive(Clone)]
 pub struct StartExpr {
     pub output: OutputExpr,
+    pub escape: Option<EscapeExpr>,
     pub expr: Expr,
 }
 
@@ -23,6 +40,14 @@ pub struct OutputExpr {
     pub right_mustache: InfoToken,
 }
 
+#[derive(Clone)]
+pub struct EscapeExpr {
+    pub left_mustache: InfoToken,
+    pub escape: InfoToken,
+    pub escaper: InfoToken,
+    pub right_mustache: InfoToken,
+}
+
 #[derive(Clone)]
 pub struct LoopExpr {
     pub loop_start: LoopStartExpr,
@@ -35,8 +60,15 @@ pub struct LoopStartExpr {
     pub left_mustache: InfoToken,
     pub r#loop: InfoToken,
     pub loop_variable: Option<ParenVariableParenExpr>,
-    pub array_accessor: ArrayAccessorExpr,
+    pub source: LoopSourceExpr,
     pub as_variable: Option<AsVariableExpr>,
+    pub joins: Vec<JoinExpr>,
+    pub where_clause: Option<WhereExpr>,
+    pub distinct: Option<DistinctExpr>,
+    pub group: Option<GroupExpr>,
+    pub sort: Option<SortExpr>,
+    pub reverse: Option<InfoToken>,
+    pub separator: Option<SeparatorExpr>,
     pub right_mustache: InfoToken,
 }
 
@@ -60,6 +92,226 @@ pub struct AsVariableExpr {
     pub variable: InfoToken,
 }
 
+#[derive(Clone)]
+pub struct IfExpr {
+    pub if_start: IfStartExpr,
+    pub block: Box<Expr>,
+    pub else_ifs: Vec<ElseIfExpr>,
+    pub r#else: Option<ElseExpr>,
+    pub if_end: EndExpr,
+}
+
+#[derive(Clone)]
+pub struct IfStartExpr {
+    pub left_mustache: InfoToken,
+    pub r#if: InfoToken,
+    pub condition: Expr,
+    pub right_mustache: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct ElseIfExpr {
+    pub left_mustache: InfoToken,
+    pub r#else: InfoToken,
+    pub r#if: InfoToken,
+    pub condition: Expr,
+    pub right_mustache: InfoToken,
+    pub block: Box<Expr>,
+}
+
+#[derive(Clone)]
+pub struct ElseExpr {
+    pub left_mustache: InfoToken,
+    pub r#else: InfoToken,
+    pub right_mustache: InfoToken,
+    pub block: Box<Expr>,
+}
+
+#[derive(Clone)]
+pub struct BinaryExpr {
+    pub left: Expr,
+    pub operator: InfoToken,
+    pub right: Expr,
+}
+
+#[derive(Clone)]
+pub struct UnaryExpr {
+    pub operator: InfoToken,
+    pub operand: Expr,
+}
+
+#[derive(Clone)]
+pub struct IsEmptyExpr {
+    pub operand: Expr,
+    pub is: InfoToken,
+    pub not: Option<InfoToken>,
+    pub empty: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct LiteralExpr {
+    pub token: InfoToken,
+}
+
+#[derive(Clone)]
+pub enum LoopSourceExpr {
+    Collection(Box<ArrayAccessorExpr>),
+    Range(Box<RangeExpr>),
+    Value(Box<LoopValueExpr>),
+}
+
+#[derive(Clone)]
+pub struct LoopValueExpr {
+    pub token: InfoToken,
+    pub value: Expr,
+    pub filters: Vec<FilterExpr>,
+}
+
+#[derive(Clone)]
+pub struct RangeExpr {
+    pub start_token: InfoToken,
+    pub start: Expr,
+    pub double_dot: InfoToken,
+    pub end_token: InfoToken,
+    pub end: Expr,
+}
+
+#[derive(Clone)]
+pub struct JoinExpr {
+    pub join: InfoToken,
+    pub array_accessor: ArrayAccessorExpr,
+    pub as_variable: Option<AsVariableExpr>,
+    pub on: InfoToken,
+    pub condition: Expr,
+}
+
+#[derive(Clone)]
+pub struct WhereExpr {
+    pub r#where: InfoToken,
+    pub condition: Expr,
+}
+
+#[derive(Clone)]
+pub struct DistinctExpr {
+    pub distinct: InfoToken,
+    pub by: Option<DistinctByExpr>,
+}
+
+#[derive(Clone)]
+pub struct DistinctByExpr {
+    pub by: InfoToken,
+    pub token: InfoToken,
+    pub key: Expr,
+}
+
+#[derive(Clone)]
+pub struct GroupExpr {
+    pub group: InfoToken,
+    pub by: InfoToken,
+    pub token: InfoToken,
+    pub key: Expr,
+    pub r#as: InfoToken,
+    pub key_variable: Option<InfoToken>,
+    pub variable: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct SortExpr {
+    pub sort: InfoToken,
+    pub by: InfoToken,
+    pub keys: Vec<SortKeyExpr>,
+}
+
+#[derive(Clone)]
+pub struct SortKeyExpr {
+    pub token: InfoToken,
+    pub value: Expr,
+    pub direction: Option<InfoToken>,
+    pub ordering: Option<InfoToken>,
+}
+
+#[derive(Clone)]
+pub struct SeparatorExpr {
+    pub separator: InfoToken,
+    pub value: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct RawExpr {
+    pub left_mustache: InfoToken,
+    pub raw: InfoToken,
+    pub right_mustache: InfoToken,
+    pub text: Option<InfoToken>,
+    pub raw_end: EndExpr,
+}
+
+#[derive(Clone)]
+pub struct IncludeExpr {
+    pub left_mustache: InfoToken,
+    pub include: InfoToken,
+    pub file_path: InfoToken,
+    pub right_mustache: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct ModeExpr {
+    pub left_mustache: InfoToken,
+    pub mode: InfoToken,
+    pub name: InfoToken,
+    pub right_mustache: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct DefineExpr {
+    pub left_mustache: InfoToken,
+    pub define: InfoToken,
+    pub name: InfoToken,
+    pub left_paren: InfoToken,
+    pub parameters: Vec<InfoToken>,
+    pub right_paren: InfoToken,
+    pub right_mustache: InfoToken,
+    pub block: Box<Expr>,
+    pub define_end: EndExpr,
+}
+
+#[derive(Clone)]
+pub struct CallExpr {
+    pub left_mustache: InfoToken,
+    pub call: InfoToken,
+    pub name: InfoToken,
+    pub arguments: ArgumentsExpr,
+    pub right_mustache: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct ExtendsExpr {
+    pub left_mustache: InfoToken,
+    pub extends: InfoToken,
+    pub file_path: InfoToken,
+    pub right_mustache: InfoToken,
+}
+
+#[derive(Clone)]
+pub struct NamedBlockExpr {
+    pub left_mustache: InfoToken,
+    pub named_block: InfoToken,
+    pub name: InfoToken,
+    pub right_mustache: InfoToken,
+    pub block: Box<Expr>,
+    pub block_end: EndExpr,
+}
+
+#[derive(Clone)]
+pub struct SetExpr {
+    pub left_mustache: InfoToken,
+    pub set: InfoToken,
+    pub name: InfoToken,
+    pub assign: InfoToken,
+    pub value: Expr,
+    pub filters: Vec<FilterExpr>,
+    pub right_mustache: InfoToken,
+}
+
 #[derive(Clone)]
 pub struct EndExpr {
     pub left_mustache: InfoToken,
@@ -109,21 +361,48 @@ pub struct AnythingExpr {
 #[derive(Clone)]
 pub struct MustacheAccessorExpr {
     pub left_mustache: InfoToken,
-    pub accessor: AccessorExpr,
+    pub value: Expr,
+    pub filters: Vec<FilterExpr>,
     pub right_mustache: InfoToken,
 }
 
+#[derive(Clone)]
+pub struct FilterExpr {
+    pub pipe: InfoToken,
+    pub name: InfoToken,
+    pub arguments: Option<ArgumentsExpr>,
+}
+
+#[derive(Clone)]
+pub struct FunctionExpr {
+    pub name: InfoToken,
+    pub arguments: ArgumentsExpr,
+}
+
+#[derive(Clone)]
+pub struct ArgumentsExpr {
+    pub left_paren: InfoToken,
+    pub arguments: Vec<Expr>,
+    pub right_paren: InfoToken,
+}
+
 #[derive(Clone)]
 pub struct ArrayAccessorExpr {
     pub variable: InfoToken,
-    pub indexes: Vec<ArrayBracketExpr>,
+    pub indexes: Vec<IndexExpr>,
     pub array_slice: Option<ArraySliceExpr>,
 }
 
 #[derive(Clone)]
 pub struct AccessorExpr {
     pub variable: InfoToken,
-    pub indexes: Vec<ArrayBracketExpr>,
+    pub indexes: Vec<IndexExpr>,
+}
+
+#[derive(Clone)]
+pub enum IndexExpr {
+    ArrayBracket(ArrayBracketExpr),
+    DotVariable(DotVariableExpr),
 }
 
 #[derive(Clone)]
@@ -138,9 +417,16 @@ pub struct ArraySliceExpr {
     pub start_index: ArraySliceIndexExpr,
     pub comma: InfoToken,
     pub end_index: ArraySliceIndexExpr,
+    pub step: Option<ArraySliceStepExpr>,
     pub right_paren: InfoToken,
 }
 
+#[derive(Clone)]
+pub struct ArraySliceStepExpr {
+    pub comma: InfoToken,
+    pub step_index: ArraySliceIndexExpr,
+}
+
 #[derive(Clone)]
 pub struct ArrayBracketExpr {
     pub left_paren: InfoToken,
@@ -151,9 +437,11 @@ pub struct ArrayBracketExpr {
 #[derive(Clone)]
 pub struct ArraySliceIndexExpr {
     pub token: InfoToken,
+    pub index: Option<Expr>,
 }
 
 #[derive(Clone)]
 pub struct ArrayBracketIndexExpr {
     pub token: InfoToken,
+    pub index: Expr,
 }
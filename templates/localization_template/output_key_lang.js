@@ -1,12 +1,11 @@
-
+// This file is generated by Hamster Wheel from localizations.csv, do not edit it by hand.
 export let localizations = {
-    
     "title": {
         "en": "Title",
-        "ar": "عنوان",
-    }, 
+        "ar": "عنوان"
+    },
     "something": {
         "en": "Something",
-        "ar": "شيء",
-    },
-}
\ No newline at end of file
+        "ar": "شيء"
+    }
+};
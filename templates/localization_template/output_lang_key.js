@@ -1,18 +1,11 @@
-
+// This file is generated by Hamster Wheel from localizations.csv, do not edit it by hand.
 export let localizations = {
-    
     "en": {
-        
-        "title": "Title", 
-        "something": "Something",
-
+        "title": "Title",
+        "something": "Something"
     },
-     
     "ar": {
-        
-        "title": "عنوان", 
-        "something": "شيء",
-
-    },
-    
-}
+        "title": "عنوان",
+        "something": "شيء"
+    }
+};